@@ -0,0 +1,239 @@
+// pieces sliding to their new squares after a move, instead of jumping there
+//
+// the board is already in the position after the move: the moved pieces are hidden on their
+// destination squares and drawn on their way there instead, and a captured piece fades out.
+// castling moves the rook along with the king, en passant fades out the pawn next to the target,
+// and a promoting pawn is already the new piece on its way.
+
+use std::time::Duration;
+
+use ggez::graphics::Canvas;
+use leben_chess::board::Board;
+use leben_chess::board::board_pos::BoardPosition;
+use leben_chess::board::piece::{Piece, PieceType};
+use leben_chess::moves::ChessMove;
+
+use crate::atlas::PieceAtlas;
+use crate::{fen, promotion, ChessBoard};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationSpeed {
+    Off,
+    Fast,
+    Normal,
+    Slow,
+}
+
+impl AnimationSpeed {
+
+    fn duration(&self) -> Duration {
+
+        match self {
+            AnimationSpeed::Off => Duration::ZERO,
+            AnimationSpeed::Fast => Duration::from_millis(120),
+            AnimationSpeed::Normal => Duration::from_millis(250),
+            AnimationSpeed::Slow => Duration::from_millis(450),
+        }
+    }
+
+    pub fn next(&self) -> Self {
+
+        match self {
+            AnimationSpeed::Off => AnimationSpeed::Fast,
+            AnimationSpeed::Fast => AnimationSpeed::Normal,
+            AnimationSpeed::Normal => AnimationSpeed::Slow,
+            AnimationSpeed::Slow => AnimationSpeed::Off,
+        }
+    }
+
+    // as written in the settings file
+    pub fn name(&self) -> &'static str {
+
+        match self {
+            AnimationSpeed::Off => "off",
+            AnimationSpeed::Fast => "fast",
+            AnimationSpeed::Normal => "normal",
+            AnimationSpeed::Slow => "slow",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+
+        match name {
+            "off" => Some(AnimationSpeed::Off),
+            "fast" => Some(AnimationSpeed::Fast),
+            "normal" => Some(AnimationSpeed::Normal),
+            "slow" => Some(AnimationSpeed::Slow),
+            _ => None,
+        }
+    }
+}
+
+pub struct Animation {
+    slides: Vec<(Piece, BoardPosition, BoardPosition)>, // piece, from, to
+    captured: Option<(Piece, BoardPosition)>,
+    elapsed: Duration,
+    duration: Duration,
+}
+
+impl Animation {
+
+    // board: the position before the move. None if animations are off
+    pub fn new(board: &Board, mv: ChessMove, speed: AnimationSpeed) -> Option<Self> {
+
+        if speed == AnimationSpeed::Off {
+            return None;
+        }
+
+        let from = mv.piece_movement.from;
+        let to = mv.piece_movement.to;
+        let piece = board.get_piece(from)?;
+
+        // what arrives on the target square, so there is no pawn turning into a queen at the end
+        let arriving = match mv.promotion {
+            Some(promotion) => Piece { piece_type: promotion::piece_type(promotion), player: piece.player },
+            None => piece,
+        };
+
+        let mut slides = vec![(arriving, from, to)];
+        let mut captured = board.get_piece(to).map(|target| (target, to));
+
+        let file_change = to.file.get() as i8 - from.file.get() as i8;
+        let rank = from.rank.get();
+
+        // the king moving two squares is castling, the rook jumps over it
+        if piece.piece_type == PieceType::King && file_change.abs() == 2 {
+
+            let (rook_from, rook_to) = if file_change > 0 {
+                (fen::square(7, rank), fen::square(5, rank))
+            } else {
+                (fen::square(0, rank), fen::square(3, rank))
+            };
+
+            if let Some(rook) = board.get_piece(rook_from) {
+                slides.push((rook, rook_from, rook_to));
+            }
+        }
+
+        // a pawn moving diagonally to an empty square takes en passant
+        if piece.piece_type == PieceType::Pawn && file_change != 0 && captured.is_none() {
+
+            let passed = fen::square(to.file.get(), rank);
+            captured = board.get_piece(passed).map(|pawn| (pawn, passed));
+        }
+
+        Some(Animation { slides, captured, elapsed: Duration::ZERO, duration: speed.duration() })
+    }
+
+    // true once the pieces have arrived
+    pub fn advance(&mut self, delta: Duration) -> bool {
+
+        self.elapsed += delta;
+        self.elapsed >= self.duration
+    }
+
+    // where the moved pieces are on the board, they are drawn by draw() until the end
+    pub fn hidden(&self) -> impl Iterator<Item = BoardPosition> + '_ {
+        self.slides.iter().map(|&(_, _, to)| to)
+    }
+
+    // 0 to 1, fast at first and slowing down towards the end
+    fn progress(&self) -> f32 {
+
+        let t = (self.elapsed.as_secs_f32() / self.duration.as_secs_f32()).min(1.0);
+        1.0 - (1.0 - t).powi(3)
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas, atlas: &PieceAtlas, board: &ChessBoard) {
+
+        let progress = self.progress();
+        let size = board.square_size();
+
+        if let Some((piece, square)) = self.captured {
+            atlas.draw_faded(canvas, piece, board.calc_square_pos(square), size, 1.0 - progress);
+        }
+
+        for &(piece, from, to) in &self.slides {
+
+            let dest = board.calc_square_pos(from).lerp(board.calc_square_pos(to), progress);
+            atlas.draw_piece(canvas, piece, dest, size);
+        }
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use leben_chess::board::piece::PlayerColor;
+    use leben_chess::moves::{PieceMovement, PromotionType};
+    use crate::fen::parse_square;
+
+    fn sq(name: &str) -> BoardPosition {
+        parse_square(name).unwrap()
+    }
+
+    fn animate_promotion(fen: &str, from: &str, to: &str, promotion: Option<PromotionType>) -> Animation {
+
+        let (board, _) = fen::parse(fen).unwrap();
+        let mv = ChessMove { piece_movement: PieceMovement { from: sq(from), to: sq(to) }, promotion };
+
+        Animation::new(&board, mv, AnimationSpeed::Normal).unwrap()
+    }
+
+    fn animate(fen: &str, from: &str, to: &str) -> Animation {
+        animate_promotion(fen, from, to, None)
+    }
+
+    #[test]
+    fn castling_moves_the_rook_too() {
+
+        let animation = animate("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1", "c1");
+        let hidden: Vec<BoardPosition> = animation.hidden().collect();
+
+        assert!(hidden == vec![sq("c1"), sq("d1")]);
+        assert!(animation.slides[1].1 == sq("a1"));
+        assert!(animation.captured.is_none());
+    }
+
+    #[test]
+    fn en_passant_fades_out_the_passed_pawn() {
+
+        let animation = animate("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5", "d6");
+
+        assert!(animation.captured.is_some_and(|(piece, square)| piece.piece_type == PieceType::Pawn && square == sq("d5")));
+    }
+
+    #[test]
+    fn captures_fade_out_on_the_target_square() {
+
+        let animation = animate("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1", "e4", "d5");
+
+        assert!(animation.captured.is_some_and(|(_, square)| square == sq("d5")));
+        assert_eq!(animation.slides.len(), 1);
+    }
+
+    #[test]
+    fn promotions_slide_the_new_piece() {
+
+        let animation = animate_promotion("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7", "b8", Some(PromotionType::Knight));
+        let (piece, _, _) = animation.slides[0];
+
+        assert!(piece.piece_type == PieceType::Knight && piece.player == PlayerColor::White);
+        assert!(animation.captured.is_some_and(|(piece, _)| piece.piece_type == PieceType::Rook));
+    }
+
+    #[test]
+    fn finishes_after_its_duration() {
+
+        let mut animation = animate("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", "e2", "e4");
+
+        assert!(!animation.advance(Duration::from_millis(100)));
+        assert!(animation.advance(Duration::from_millis(200)));
+        assert_eq!(animation.progress(), 1.0);
+    }
+
+}
@@ -0,0 +1,204 @@
+// piece sets, and their images loaded once instead of from disk on every frame
+//
+// a piece set is a directory under resources/pieces with a manifest.txt:
+//
+//   name = Classic
+//   format = png        # or svg, the extension of the 12 images
+//   wK = king_white.svg # optional, for files not named wK.png etc.
+//
+// images are named after the color and the piece: wp, wN, wB, wR, wQ, wK, and bp ... bK.
+// SVGs are rendered once when the set is loaded.
+//
+// the pieces on the board are batched: one InstanceArray per kind of piece,
+// so the whole position takes at most 12 draw calls
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use ggez::graphics::{Canvas, Color, DrawParam, Image, ImageFormat, InstanceArray};
+use ggez::{Context, GameError, GameResult};
+use ggez::glam::*;
+use leben_chess::board::piece::{Piece, PieceType, PlayerColor};
+use resvg::{tiny_skia, usvg};
+
+use crate::config;
+
+
+pub const PIECE_TYPES: [PieceType; 6] = [PieceType::Pawn, PieceType::Knight, PieceType::Bishop, PieceType::Rook, PieceType::Queen, PieceType::King];
+const COLORS: [PlayerColor; 2] = [PlayerColor::White, PlayerColor::Black];
+
+// size SVG pieces are rendered at, about the largest a square gets on a big screen
+const SVG_SIZE: u32 = 256;
+
+pub struct PieceSet {
+    pub id: String, // directory name
+    pub name: String,
+    files: Vec<PathBuf>, // indexed by index()
+}
+
+pub struct PieceAtlas {
+    images: Vec<Image>, // indexed by index()
+    batches: Vec<InstanceArray>, // same order as images
+}
+
+// file name without the extension
+fn code(piece: Piece) -> &'static str {
+
+    match (piece.piece_type, piece.player) {
+        (PieceType::Pawn, PlayerColor::White) => "wp",
+        (PieceType::Knight, PlayerColor::White) => "wN",
+        (PieceType::Bishop, PlayerColor::White) => "wB",
+        (PieceType::Rook, PlayerColor::White) => "wR",
+        (PieceType::Queen, PlayerColor::White) => "wQ",
+        (PieceType::King, PlayerColor::White) => "wK",
+        (PieceType::Pawn, PlayerColor::Black) => "bp",
+        (PieceType::Knight, PlayerColor::Black) => "bN",
+        (PieceType::Bishop, PlayerColor::Black) => "bB",
+        (PieceType::Rook, PlayerColor::Black) => "bR",
+        (PieceType::Queen, PlayerColor::Black) => "bQ",
+        (PieceType::King, PlayerColor::Black) => "bK",
+    }
+}
+
+fn index(piece: Piece) -> usize {
+
+    let color = match piece.player {
+        PlayerColor::White => 0,
+        PlayerColor::Black => 1,
+    };
+    let piece_type = match piece.piece_type {
+        PieceType::Pawn => 0,
+        PieceType::Knight => 1,
+        PieceType::Bishop => 2,
+        PieceType::Rook => 3,
+        PieceType::Queen => 4,
+        PieceType::King => 5,
+    };
+
+    color*6 + piece_type
+}
+
+// every piece, in index() order
+fn all_pieces() -> impl Iterator<Item = Piece> {
+    COLORS.into_iter().flat_map(|player| PIECE_TYPES.into_iter().map(move |piece_type| Piece { piece_type, player }))
+}
+
+// all piece sets in resources/pieces, sorted by directory name
+pub fn piece_sets(ctx: &Context) -> Vec<PieceSet> {
+
+    config::manifests(ctx, "/pieces")
+        .into_iter()
+        .map(|manifest| {
+
+            let format = manifest.entries.get("format").map_or("png", String::as_str);
+
+            let files = all_pieces()
+                .map(|piece| {
+                    let file = manifest.entries.get(code(piece)).cloned().unwrap_or_else(|| format!("{}.{}", code(piece), format));
+                    manifest.dir.join(file)
+                })
+                .collect();
+
+            PieceSet {
+                name: manifest.entries.get("name").cloned().unwrap_or_else(|| manifest.id.clone()),
+                id: manifest.id,
+                files,
+            }
+        })
+        .collect()
+}
+
+fn load_image(ctx: &Context, path: &Path) -> GameResult<Image> {
+
+    let is_svg = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("svg"));
+
+    if is_svg {
+        render_svg(ctx, path)
+    } else {
+        Image::from_path(ctx, path)
+    }
+}
+
+fn render_svg(ctx: &Context, path: &Path) -> GameResult<Image> {
+
+    let svg_error = |e: &dyn std::fmt::Display| GameError::ResourceLoadError(format!("{}: {}", path.display(), e));
+
+    let mut data = Vec::new();
+    ctx.fs.open(path)?.read_to_end(&mut data)?;
+
+    let tree = usvg::Tree::from_data(&data, &usvg::Options::default()).map_err(|e| svg_error(&e))?;
+    let mut pixmap = tiny_skia::Pixmap::new(SVG_SIZE, SVG_SIZE).ok_or_else(|| svg_error(&"no pixmap"))?;
+
+    let size = tree.size();
+    let scale = tiny_skia::Transform::from_scale(SVG_SIZE as f32 / size.width(), SVG_SIZE as f32 / size.height());
+    resvg::render(&tree, scale, &mut pixmap.as_mut());
+
+    // tiny-skia premultiplies the alpha, ggez images don't
+    let pixels: Vec<u8> = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+
+    Ok(Image::from_pixels(ctx, &pixels, ImageFormat::Rgba8UnormSrgb, SVG_SIZE, SVG_SIZE))
+}
+
+impl PieceAtlas {
+
+    pub fn new(ctx: &Context, set: &PieceSet) -> GameResult<Self> {
+
+        let mut images = Vec::new();
+        let mut batches = Vec::new();
+
+        for file in &set.files {
+            let image = load_image(ctx, file)?;
+            batches.push(InstanceArray::new(ctx, image.clone()));
+            images.push(image);
+        }
+
+        Ok(PieceAtlas { images, batches })
+    }
+
+    fn param(image: &Image, dest: Vec2, size: f32) -> DrawParam {
+
+        let scale = Vec2::new(size / image.width() as f32, size / image.height() as f32);
+        DrawParam::default().dest(dest).scale(scale)
+    }
+
+    // a single piece, e.g. under the cursor while dragging. dest: upper left corner
+    pub fn draw_piece(&self, canvas: &mut Canvas, piece: Piece, dest: Vec2, size: f32) {
+
+        let image = &self.images[index(piece)];
+        canvas.draw(image, PieceAtlas::param(image, dest, size));
+    }
+
+    // alpha: 1 is opaque, 0 invisible
+    pub fn draw_faded(&self, canvas: &mut Canvas, piece: Piece, dest: Vec2, size: f32, alpha: f32) {
+
+        let image = &self.images[index(piece)];
+        canvas.draw(image, PieceAtlas::param(image, dest, size).color(Color::new(1.0, 1.0, 1.0, alpha)));
+    }
+
+    // all pieces of a position at once
+    pub fn draw_pieces(&mut self, canvas: &mut Canvas, pieces: impl IntoIterator<Item = (Piece, Vec2)>, size: f32) {
+
+        for batch in &mut self.batches {
+            batch.clear();
+        }
+
+        for (piece, dest) in pieces {
+            let i = index(piece);
+            self.batches[i].push(PieceAtlas::param(&self.images[i], dest, size));
+        }
+
+        for batch in &self.batches {
+            if !batch.instances().is_empty() {
+                canvas.draw(batch, DrawParam::default());
+            }
+        }
+    }
+
+}
@@ -0,0 +1,26 @@
+// rectangular button with a centered label, hit-testing is done with Rect::contains
+
+use ggez::graphics::{self, Canvas, Color, DrawParam, Rect};
+use ggez::{Context, GameResult};
+use ggez::glam::*;
+
+
+pub fn draw_button(ctx: &mut Context, canvas: &mut Canvas, button: Rect, label: &str, color: Color) -> GameResult {
+
+    let button_mesh = graphics::Mesh::new_rectangle(
+        ctx,
+        graphics::DrawMode::fill(),
+        graphics::Rect::new(0.0, 0.0, button.w, button.h),
+        color,
+    )?;
+    canvas.draw(&button_mesh, Vec2::new(button.x, button.y));
+
+    let mut text = graphics::Text::new(label);
+    text.set_scale(button.h*0.3);
+    let text_size = text.measure(ctx)?;
+    let text_x = button.x + (button.w - text_size.x)/2.0;
+    let text_y = button.y + (button.h - text_size.y)/2.0;
+    canvas.draw(&text, DrawParam::default().dest([text_x, text_y]));
+
+    Ok(())
+}
@@ -0,0 +1,151 @@
+// command-line interface
+//
+// chess-gui                         local hot-seat game (default)
+// chess-gui local
+// chess-gui host 0.0.0.0:8080       wait for an opponent to connect
+// chess-gui join 192.168.1.5:8080   connect to an opponent who is hosting
+// chess-gui auto 127.0.0.1:8080     join if someone is hosting, otherwise host
+// chess-gui --pgn game.pgn          step through a saved game
+// chess-gui --time 5 --increment 3  5 minutes per side, 3 seconds added per move
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use leben_chess::board::piece::PlayerColor;
+
+use crate::clock::TimeControl;
+
+
+const MAX_MINUTES: f32 = 600.0;
+const MAX_SECONDS: f32 = 60.0;
+
+#[derive(Parser, Debug)]
+#[command(name = "chess-gui", about = "Chess GUI for local hot-seat and network games")]
+pub struct Cli {
+
+    #[command(subcommand)]
+    pub mode: Option<Mode>,
+
+    /// Color you play when hosting a network game.
+    /// Picked at random if omitted, the joining player gets the other color
+    #[arg(long, value_enum, global = true)]
+    pub color: Option<ColorArg>,
+
+    /// Starting position in FEN-notation, instead of the default board.
+    /// White has to be the side to move, every king and rook on its starting square keeps
+    /// its castling rights and no en passant capture can be available
+    #[arg(long, global = true)]
+    pub fen: Option<String>,
+
+    /// Minutes on each clock, at most 600. The game is untimed if omitted.
+    /// For network games the host's time control is used
+    #[arg(long, global = true, value_parser = minutes)]
+    pub time: Option<f32>,
+
+    /// Seconds added to the clock after every move (Fischer), at most 60
+    #[arg(long, global = true, value_parser = seconds, requires = "time", conflicts_with = "delay")]
+    pub increment: Option<f32>,
+
+    /// Seconds of the time spent on a move that are given back after it (Bronstein), at most 60
+    #[arg(long, global = true, value_parser = seconds, requires = "time")]
+    pub delay: Option<f32>,
+
+    /// PGN file to step through with the arrow keys.
+    /// Files can also be dropped onto the window
+    #[arg(long)]
+    pub pgn: Option<PathBuf>,
+
+    /// Initial height of the window in pixels, the side panel makes it a bit wider than that.
+    /// The window can be resized later on
+    #[arg(long, global = true, default_value_t = 800.0, value_parser = positive)]
+    pub window_size: f32,
+
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Mode {
+
+    /// Two players taking turns on the same machine
+    Local,
+
+    /// Listen for an opponent on the given address
+    Host {
+        #[arg(default_value = "0.0.0.0:8080")]
+        bind: String,
+    },
+
+    /// Connect to an opponent who is hosting on the given address
+    Join {
+        addr: String,
+    },
+
+    /// Try to join, and host on the same address if nobody is listening
+    Auto {
+        #[arg(default_value = "127.0.0.1:8080")]
+        addr: String,
+    },
+
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum ColorArg {
+    White,
+    Black,
+}
+
+impl From<ColorArg> for PlayerColor {
+
+    fn from(color: ColorArg) -> Self {
+        match color {
+            ColorArg::White => PlayerColor::White,
+            ColorArg::Black => PlayerColor::Black,
+        }
+    }
+}
+
+// times are turned into Durations, which can't be negative, and the clock times
+// have to fit into a protocol message (see protocol.rs) in network games
+fn bounded(arg: &str, max: f32) -> Result<f32, String> {
+
+    match arg.parse::<f32>() {
+        Ok(value) if value.is_finite() && (0.0..=max).contains(&value) => Ok(value),
+        _ => Err(format!("{:?} is not a number from 0 to {}", arg, max)),
+    }
+}
+
+fn minutes(arg: &str) -> Result<f32, String> {
+    bounded(arg, MAX_MINUTES)
+}
+
+fn seconds(arg: &str) -> Result<f32, String> {
+    bounded(arg, MAX_SECONDS)
+}
+
+// window dimensions, a window of size zero can't be created
+fn positive(arg: &str) -> Result<f32, String> {
+
+    match arg.parse::<f32>() {
+        Ok(value) if value.is_finite() && value > 0.0 => Ok(value),
+        _ => Err(format!("{:?} is not a positive number", arg)),
+    }
+}
+
+impl Cli {
+
+    pub fn mode(&self) -> Mode {
+        self.mode.clone().unwrap_or(Mode::Local)
+    }
+
+    pub fn time_control(&self) -> Option<TimeControl> {
+
+        let base = Duration::from_secs_f32(self.time? * 60.0);
+
+        Some(match (self.increment, self.delay) {
+            (Some(increment), _) => TimeControl::Fischer { base, increment: Duration::from_secs_f32(increment) },
+            (None, Some(delay)) => TimeControl::Bronstein { base, delay: Duration::from_secs_f32(delay) },
+            (None, None) => TimeControl::SuddenDeath { base },
+        })
+    }
+
+}
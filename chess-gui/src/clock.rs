@@ -0,0 +1,287 @@
+// chess clock: sudden death, Fischer increment or Bronstein delay
+//
+// the clock switches on every move, White's clock starts with the first move.
+// Fischer: the increment is added after every move
+// Bronstein: the clock runs down as usual, after every move the time spent on it is given back,
+// up to the delay. So the flag falls at exactly the remaining time, the delay can't save a move
+
+use std::time::{Duration, Instant};
+
+use ggez::graphics::{self, Canvas, Color, DrawParam, Rect};
+use ggez::{Context, GameResult};
+use ggez::glam::*;
+use leben_chess::board::Board;
+use leben_chess::board::piece::{PieceType, PlayerColor};
+
+use crate::fen::square;
+use crate::opponent_color;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeControl {
+    SuddenDeath { base: Duration },
+    Fischer { base: Duration, increment: Duration },
+    Bronstein { base: Duration, delay: Duration },
+}
+
+pub struct Clock {
+    control: TimeControl,
+    remaining: [Duration; 2], // White, Black, as of the start of the current turn
+    running: Option<(PlayerColor, Instant)>, // whose clock runs, since when
+}
+
+fn index(color: PlayerColor) -> usize {
+    match color {
+        PlayerColor::White => 0,
+        PlayerColor::Black => 1,
+    }
+}
+
+impl TimeControl {
+
+    fn base(&self) -> Duration {
+        match *self {
+            TimeControl::SuddenDeath { base } => base,
+            TimeControl::Fischer { base, .. } => base,
+            TimeControl::Bronstein { base, .. } => base,
+        }
+    }
+
+    // what a move that took `spent` costs in the end
+    fn cost(&self, remaining: Duration, spent: Duration) -> Duration {
+        match *self {
+            TimeControl::SuddenDeath { .. } => remaining.saturating_sub(spent),
+            TimeControl::Fischer { increment, .. } => remaining.saturating_sub(spent) + increment,
+            TimeControl::Bronstein { delay, .. } => remaining.saturating_sub(spent) + spent.min(delay),
+        }
+    }
+
+}
+
+impl Clock {
+
+    pub fn new(control: TimeControl) -> Self {
+        Clock { control, remaining: [control.base(); 2], running: None }
+    }
+
+    pub fn remaining(&self, color: PlayerColor) -> Duration {
+        self.remaining_at(color, Instant::now())
+    }
+
+    // the clock logic takes the current time as an argument, so that the tests can control it
+    fn remaining_at(&self, color: PlayerColor, now: Instant) -> Duration {
+
+        let remaining = self.remaining[index(color)];
+
+        match self.running {
+            Some((running, since)) if running == color => remaining.saturating_sub(now.saturating_duration_since(since)),
+            _ => remaining,
+        }
+    }
+
+    // mover just made a move, now it's the opponent's turn
+    pub fn switch(&mut self, mover: PlayerColor) {
+        self.switch_at(mover, Instant::now());
+    }
+
+    fn switch_at(&mut self, mover: PlayerColor, now: Instant) {
+
+        // White's first move is made before any clock runs, it still earns the increment
+        let spent = match self.running {
+            Some((running, since)) if running == mover => now.saturating_duration_since(since),
+            _ => Duration::ZERO,
+        };
+
+        let i = index(mover);
+        self.remaining[i] = self.control.cost(self.remaining[i], spent);
+
+        self.running = Some((opponent_color(mover), now));
+    }
+
+    // after takebacks: keep the times, but the clock of the side to move runs
+    pub fn restart(&mut self, color: PlayerColor) {
+
+        self.stop();
+        self.running = Some((color, Instant::now()));
+    }
+
+    pub fn stop(&mut self) {
+
+        if let Some((running, _)) = self.running {
+            self.remaining[index(running)] = self.remaining(running);
+        }
+        self.running = None;
+    }
+
+    // the opponent's own measurement of their time is what counts
+    pub fn set_remaining(&mut self, color: PlayerColor, remaining: Duration) {
+        self.remaining[index(color)] = remaining;
+    }
+
+    // the side whose time ran out
+    pub fn flagged(&self) -> Option<PlayerColor> {
+        self.flagged_at(Instant::now())
+    }
+
+    fn flagged_at(&self, now: Instant) -> Option<PlayerColor> {
+
+        match self.running {
+            Some((running, _)) if self.remaining_at(running, now).is_zero() => Some(running),
+            _ => None,
+        }
+    }
+
+    pub fn draw(&self, ctx: &mut Context, canvas: &mut Canvas, area: Rect, color: PlayerColor) -> GameResult {
+
+        let running = matches!(self.running, Some((running, _)) if running == color);
+        let remaining = self.remaining(color);
+
+        let background = match (running, remaining.is_zero()) {
+            (_, true) => Color::from_rgb(150, 0, 0),
+            (true, false) => Color::from_rgb(230, 230, 230),
+            (false, false) => Color::from_rgb(90, 90, 90),
+        };
+
+        let mesh = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(0.0, 0.0, area.w, area.h),
+            background,
+        )?;
+        canvas.draw(&mesh, Vec2::new(area.x, area.y));
+
+        let mut text = graphics::Text::new(format_time(remaining));
+        text.set_scale(area.h*0.7);
+        let text_size = text.measure(ctx)?;
+
+        let text_color = if running { Color::BLACK } else { Color::WHITE };
+        let text_x = area.x + (area.w - text_size.x)/2.0;
+        let text_y = area.y + (area.h - text_size.y)/2.0;
+        canvas.draw(&text, DrawParam::default().dest([text_x, text_y]).color(text_color));
+
+        Ok(())
+    }
+
+}
+
+// 1:05:00, 4:59, and tenths of a second in the last 10 seconds: 0:09.8
+pub fn format_time(time: Duration) -> String {
+
+    let seconds = time.as_secs();
+
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    } else if seconds >= 10 {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    } else {
+        format!("0:{:02}.{}", seconds, time.subsec_millis() / 100)
+    }
+}
+
+// a lone king, or a king with a single bishop or knight, can't checkmate.
+// if the side whose time ran out is up against that, the game is drawn instead
+pub fn can_checkmate(board: &Board, color: PlayerColor) -> bool {
+
+    let mut minor_pieces = 0;
+
+    for i in 0..64 {
+
+        let Some(piece) = board.get_piece(square(i % 8, i / 8)) else {
+            continue;
+        };
+
+        if piece.player != color {
+            continue;
+        }
+
+        match piece.piece_type {
+            PieceType::King => {}
+            PieceType::Bishop | PieceType::Knight => minor_pieces += 1,
+            PieceType::Pawn | PieceType::Rook | PieceType::Queen => return true,
+        }
+    }
+
+    minor_pieces >= 2
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const BASE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn fischer_increment_after_every_move() {
+
+        let mut clock = Clock::new(TimeControl::Fischer { base: BASE, increment: Duration::from_secs(2) });
+
+        clock.switch(PlayerColor::White);
+        assert_eq!(clock.remaining(PlayerColor::White), Duration::from_secs(62));
+
+        clock.switch(PlayerColor::Black);
+        let black = clock.remaining(PlayerColor::Black);
+        assert!(black > Duration::from_millis(61_900) && black <= Duration::from_secs(62), "{:?}", black);
+
+        clock.switch(PlayerColor::White);
+        assert!(clock.remaining(PlayerColor::White) > Duration::from_millis(63_900));
+    }
+
+    #[test]
+    fn no_increment_in_sudden_death() {
+
+        let mut clock = Clock::new(TimeControl::SuddenDeath { base: BASE });
+
+        clock.switch(PlayerColor::White);
+        assert_eq!(clock.remaining(PlayerColor::White), BASE);
+
+        clock.switch(PlayerColor::Black);
+        assert!(clock.remaining(PlayerColor::Black) <= BASE);
+    }
+
+    #[test]
+    fn bronstein_gives_back_the_time_spent_up_to_the_delay() {
+
+        let mut clock = Clock::new(TimeControl::Bronstein { base: BASE, delay: Duration::from_secs(2) });
+        let start = Instant::now();
+        let at = |seconds: u64| start + Duration::from_secs(seconds);
+
+        // nothing to give back for White's first move, Black's clock starts
+        clock.switch_at(PlayerColor::White, start);
+        assert_eq!(clock.remaining_at(PlayerColor::White, start), BASE);
+
+        // the running clock counts down from the first second on
+        assert_eq!(clock.remaining_at(PlayerColor::Black, at(1)), BASE - Duration::from_secs(1));
+
+        // a move within the delay is free
+        clock.switch_at(PlayerColor::Black, at(1));
+        assert_eq!(clock.remaining_at(PlayerColor::Black, at(1)), BASE);
+
+        // a longer one costs what's over the delay
+        assert_eq!(clock.remaining_at(PlayerColor::White, at(6)), BASE - Duration::from_secs(5));
+        clock.switch_at(PlayerColor::White, at(6));
+        assert_eq!(clock.remaining_at(PlayerColor::White, at(6)), BASE - Duration::from_secs(3));
+    }
+
+    #[test]
+    fn bronstein_flag_falls_at_the_remaining_time() {
+
+        let mut clock = Clock::new(TimeControl::Bronstein { base: BASE, delay: Duration::from_secs(2) });
+        let start = Instant::now();
+
+        clock.switch_at(PlayerColor::White, start);
+
+        assert_eq!(clock.flagged_at(start + BASE - Duration::from_millis(1)), None);
+        assert_eq!(clock.flagged_at(start + BASE), Some(PlayerColor::Black));
+    }
+
+    #[test]
+    fn formats_times() {
+
+        assert_eq!(format_time(Duration::from_secs(3900)), "1:05:00");
+        assert_eq!(format_time(Duration::from_secs(299)), "4:59");
+        assert_eq!(format_time(Duration::from_millis(9_850)), "0:09.8");
+    }
+
+}
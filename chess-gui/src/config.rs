@@ -0,0 +1,123 @@
+// the small text format of the settings file and of the theme manifests
+//
+//   # a comment
+//   name = Classic
+//   light = #f0d9b5
+//
+// one `key = value` per line, blank lines and lines starting with # are skipped.
+// keys are case-sensitive, a key given twice keeps the last value
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use ggez::Context;
+
+
+pub fn parse(text: &str) -> HashMap<String, String> {
+
+    let mut entries = HashMap::new();
+
+    for line in text.lines() {
+
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        // lines without a = are ignored rather than refusing the whole file
+        if let Some((key, value)) = line.split_once('=') {
+            entries.insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+
+    entries
+}
+
+pub fn write(entries: &[(&str, String)]) -> String {
+
+    let mut text = String::new();
+
+    for (key, value) in entries {
+        text.push_str(&format!("{} = {}\n", key, value));
+    }
+
+    text
+}
+
+// a file in the resources or the user config directory, None if it can't be read
+pub fn load(ctx: &Context, path: impl AsRef<Path>) -> Option<HashMap<String, String>> {
+
+    let mut text = String::new();
+    ctx.fs.open(path).ok()?.read_to_string(&mut text).ok()?;
+
+    Some(parse(&text))
+}
+
+// a directory with a manifest.txt, e.g. resources/pieces/classic
+pub struct Manifest {
+    pub id: String, // the directory name, which is what the settings file remembers
+    pub dir: PathBuf,
+    pub entries: HashMap<String, String>,
+}
+
+// every subdirectory of dir with a manifest.txt, sorted by name
+pub fn manifests(ctx: &Context, dir: &str) -> Vec<Manifest> {
+
+    let Ok(paths) = ctx.fs.read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut found: Vec<_> = paths
+        .filter(|path| ctx.fs.is_dir(path))
+        .filter_map(|path| {
+            let id = path.file_name()?.to_string_lossy().to_string();
+            let entries = load(ctx, path.join("manifest.txt"))?;
+            Some(Manifest { id, dir: path, entries })
+        })
+        .collect();
+
+    // the same directory can show up in more than one of ggez's resource roots
+    found.sort_by(|a, b| a.id.cmp(&b.id));
+    found.dedup_by(|a, b| a.id == b.id);
+
+    found
+}
+
+pub fn parse_bool(value: &str) -> Option<bool> {
+
+    match value {
+        "true" | "yes" | "on" => Some(true),
+        "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn parses_keys_and_values() {
+
+        let entries = parse("# piece set\nname = Classic Wood \n\nformat=svg\nnot a setting\nname = Wood\n");
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries["name"], "Wood");
+        assert_eq!(entries["format"], "svg");
+    }
+
+    #[test]
+    fn written_settings_read_back() {
+
+        let text = write(&[("piece_set", "classic".to_string()), ("show_legal_moves", "false".to_string())]);
+        let entries = parse(&text);
+
+        assert_eq!(entries["piece_set"], "classic");
+        assert_eq!(entries.get("show_legal_moves").and_then(|value| parse_bool(value)), Some(false));
+    }
+
+}
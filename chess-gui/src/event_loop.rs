@@ -0,0 +1,121 @@
+// our own version of ggez::event::run
+//
+// ggez doesn't pass dropped files on to the EventHandler, so the main loop is done here:
+// every window event is fed to ggez first (event::process_event), then dispatched
+// to the GameState the same way ggez does it, plus DroppedFile for PGN files.
+
+use ggez::event::{self, EventHandler};
+use ggez::input::keyboard::KeyInput;
+use ggez::winit::event::{ElementState, Event, KeyboardInput, WindowEvent};
+use ggez::winit::event_loop::{ControlFlow, EventLoop};
+use ggez::{Context, GameResult};
+
+use crate::GameState;
+
+
+// like ggez: print the error and stop the game
+fn check(result: GameResult, control_flow: &mut ControlFlow) {
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        *control_flow = ControlFlow::Exit;
+    }
+}
+
+pub fn run(mut ctx: Context, event_loop: EventLoop<()>, mut state: GameState) -> ! {
+
+    event_loop.run(move |mut event, _, control_flow| {
+
+        let ctx = &mut ctx;
+
+        if ctx.quit_requested {
+            ctx.quit_requested = false;
+            if let Ok(false) = state.quit_event(ctx) {
+                ctx.continuing = false;
+            }
+        }
+        if !ctx.continuing {
+            *control_flow = ControlFlow::Exit;
+            return;
+        }
+
+        *control_flow = ControlFlow::Poll;
+
+        event::process_event(ctx, &mut event);
+
+        match event {
+
+            Event::WindowEvent { event, .. } => match event {
+
+                WindowEvent::Resized(size) => {
+                    check(state.resize_event(ctx, size.width as f32, size.height as f32), control_flow);
+                }
+                WindowEvent::CloseRequested => {
+                    if let Ok(false) = state.quit_event(ctx) {
+                        ctx.continuing = false;
+                    }
+                }
+                WindowEvent::DroppedFile(path) => {
+                    check(state.file_dropped_event(ctx, &path), control_flow);
+                }
+                WindowEvent::ReceivedCharacter(c) => {
+                    check(state.text_input_event(ctx, c), control_flow);
+                }
+                WindowEvent::KeyboardInput { input: KeyboardInput { state: key_state, virtual_keycode, scancode, .. }, .. } => {
+
+                    let input = KeyInput { scancode, keycode: virtual_keycode, mods: ctx.keyboard.active_mods() };
+
+                    match key_state {
+                        ElementState::Pressed => {
+                            let repeated = ctx.keyboard.is_key_repeated();
+                            check(state.key_down_event(ctx, input, repeated), control_flow);
+                        }
+                        ElementState::Released => check(state.key_up_event(ctx, input), control_flow),
+                    }
+                }
+                WindowEvent::MouseInput { state: button_state, button, .. } => {
+
+                    let position = ctx.mouse.position();
+
+                    match button_state {
+                        ElementState::Pressed => check(state.mouse_button_down_event(ctx, button, position.x, position.y), control_flow),
+                        ElementState::Released => check(state.mouse_button_up_event(ctx, button, position.x, position.y), control_flow),
+                    }
+                }
+                WindowEvent::CursorMoved { .. } => {
+                    let position = ctx.mouse.position();
+                    let delta = ctx.mouse.last_delta();
+                    check(state.mouse_motion_event(ctx, position.x, position.y, delta.x, delta.y), control_flow);
+                }
+                _ => {}
+            }
+
+            Event::MainEventsCleared => {
+
+                ctx.time.tick();
+
+                check(state.update(ctx), control_flow);
+
+                if let Err(e) = ctx.gfx.begin_frame() {
+                    eprintln!("Error on begin_frame: {}", e);
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+
+                check(state.draw(ctx), control_flow);
+
+                if let Err(e) = ctx.gfx.end_frame() {
+                    eprintln!("Error on end_frame: {}", e);
+                    *control_flow = ControlFlow::Exit;
+                }
+
+                // deltas and "just pressed" are calculated per frame
+                ctx.mouse.reset_delta();
+                ctx.keyboard.save_keyboard_state();
+                ctx.mouse.save_mouse_state();
+            }
+
+            _ => {}
+        }
+    })
+}
@@ -0,0 +1,686 @@
+// FEN-notation: reading and writing complete positions
+//
+// rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1
+// piece placement, side to move, castling rights, en passant target square,
+// halfmove clock (for the fifty-move rule) and fullmove number
+//
+// the chess lib tracks castling and en passant internally but doesn't expose them,
+// so everything but the piece placement is tracked next to the ChessGame in PositionInfo
+
+use std::fmt;
+
+use leben_chess::board::Board;
+use leben_chess::board::board_pos::BoardPosition;
+use leben_chess::board::piece::{Piece, PieceType, PlayerColor};
+use leben_chess::chess::ChessGame;
+use leben_chess::moves::ChessMove;
+
+use crate::opponent_color;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CastlingRights {
+    pub white_kingside: bool,
+    pub white_queenside: bool,
+    pub black_kingside: bool,
+    pub black_queenside: bool,
+}
+
+// the FEN fields after the piece placement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PositionInfo {
+    pub active_player: PlayerColor,
+    pub castling: CastlingRights,
+    pub en_passant: Option<BoardPosition>,
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    WrongFieldCount(usize),
+    InvalidPlacement(String),
+    InvalidSideToMove(String),
+    InvalidCastling(String),
+    InvalidEnPassant(String),
+    InvalidClock(String),
+    InvalidKings,
+    BlackToMove,
+    UnsupportedCastling(CastlingRights), // the rights a new game would have instead
+    UnsupportedEnPassant(String),
+}
+
+impl fmt::Display for FenError {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FenError::WrongFieldCount(count) => write!(f, "expected 6 fields (or 4, or only the placement), got {}", count),
+            FenError::InvalidPlacement(reason) => write!(f, "invalid piece placement: {}", reason),
+            FenError::InvalidSideToMove(side) => write!(f, "invalid side to move {:?}", side),
+            FenError::InvalidCastling(reason) => write!(f, "invalid castling rights: {}", reason),
+            FenError::InvalidEnPassant(square) => write!(f, "invalid en passant square {:?}", square),
+            FenError::InvalidClock(clock) => write!(f, "invalid move counter {:?}", clock),
+            FenError::InvalidKings => write!(f, "each side needs exactly one king"),
+            FenError::BlackToMove => write!(f, "games can only start with White to move"),
+            FenError::UnsupportedCastling(rights) => write!(f, "a king and rook on their starting squares can always castle in a new game, the castling field has to be {:?}", rights.to_string()),
+            FenError::UnsupportedEnPassant(square) => write!(f, "en passant on {} can't be played as the first move of a game", square),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+
+pub fn square(file: u8, rank: u8) -> BoardPosition {
+    BoardPosition::try_from((file, rank)).unwrap()
+}
+
+pub fn square_name(pos: BoardPosition) -> String {
+    format!("{}{}", (b'a' + pos.file.get()) as char, pos.rank.get() + 1)
+}
+
+pub fn parse_square(name: &str) -> Option<BoardPosition> {
+
+    let bytes = name.as_bytes();
+
+    if bytes.len() != 2 || !(b'a'..=b'h').contains(&bytes[0]) || !(b'1'..=b'8').contains(&bytes[1]) {
+        return None;
+    }
+
+    Some(square(bytes[0] - b'a', bytes[1] - b'1'))
+}
+
+pub fn piece_char(piece: Piece) -> char {
+
+    let c = match piece.piece_type {
+        PieceType::Pawn => 'p',
+        PieceType::Knight => 'n',
+        PieceType::Bishop => 'b',
+        PieceType::Rook => 'r',
+        PieceType::Queen => 'q',
+        PieceType::King => 'k',
+    };
+
+    match piece.player {
+        PlayerColor::White => c.to_ascii_uppercase(),
+        PlayerColor::Black => c,
+    }
+}
+
+fn char_piece(c: char) -> Option<Piece> {
+
+    let piece_type = match c.to_ascii_lowercase() {
+        'p' => PieceType::Pawn,
+        'n' => PieceType::Knight,
+        'b' => PieceType::Bishop,
+        'r' => PieceType::Rook,
+        'q' => PieceType::Queen,
+        'k' => PieceType::King,
+        _ => return None,
+    };
+
+    let player = if c.is_ascii_uppercase() { PlayerColor::White } else { PlayerColor::Black };
+
+    Some(Piece {piece_type, player})
+}
+
+fn is_piece(board: &Board, pos: BoardPosition, piece_type: PieceType, player: PlayerColor) -> bool {
+
+    match board.get_piece(pos) {
+        Some(piece) => piece.piece_type == piece_type && piece.player == player,
+        None => false,
+    }
+}
+
+fn empty_board() -> Board {
+
+    // the chess lib only hands out the default position, so clear that one
+    let mut board = Board::default_board();
+
+    for file in 0..8 {
+        for rank in 0..8 {
+            board.set_piece(square(file, rank), None);
+        }
+    }
+
+    board
+}
+
+
+impl CastlingRights {
+
+    pub fn none() -> Self {
+        CastlingRights { white_kingside: false, white_queenside: false, black_kingside: false, black_queenside: false }
+    }
+
+    // every right whose king and rook are still on their starting squares
+    fn from_board(board: &Board) -> Self {
+
+        let white_king = is_piece(board, square(4, 0), PieceType::King, PlayerColor::White);
+        let black_king = is_piece(board, square(4, 7), PieceType::King, PlayerColor::Black);
+
+        CastlingRights {
+            white_kingside: white_king && is_piece(board, square(7, 0), PieceType::Rook, PlayerColor::White),
+            white_queenside: white_king && is_piece(board, square(0, 0), PieceType::Rook, PlayerColor::White),
+            black_kingside: black_king && is_piece(board, square(7, 7), PieceType::Rook, PlayerColor::Black),
+            black_queenside: black_king && is_piece(board, square(0, 7), PieceType::Rook, PlayerColor::Black),
+        }
+    }
+
+    fn parse(field: &str, board: &Board) -> Result<Self, FenError> {
+
+        let mut rights = CastlingRights::none();
+
+        if field == "-" {
+            return Ok(rights);
+        }
+
+        // "KQkq" in that order, each letter at most once
+        let mut remaining = "KQkq";
+
+        for c in field.chars() {
+
+            match remaining.find(c) {
+                Some(index) => remaining = &remaining[index + 1..],
+                None => return Err(FenError::InvalidCastling(format!("{:?} is not a subset of \"KQkq\" in order", field))),
+            }
+
+            match c {
+                'K' => rights.white_kingside = true,
+                'Q' => rights.white_queenside = true,
+                'k' => rights.black_kingside = true,
+                'q' => rights.black_queenside = true,
+                _ => unreachable!(),
+            }
+        }
+
+        // a right can only exist while the king and rook haven't moved
+        let possible = CastlingRights::from_board(board);
+
+        if (rights.white_kingside && !possible.white_kingside)
+            || (rights.white_queenside && !possible.white_queenside)
+            || (rights.black_kingside && !possible.black_kingside)
+            || (rights.black_queenside && !possible.black_queenside) {
+            return Err(FenError::InvalidCastling(format!("{:?} doesn't match the king and rook squares", field)));
+        }
+
+        Ok(rights)
+    }
+
+    fn remove_for_square(&mut self, pos: BoardPosition) {
+
+        // a king or rook moving from, or a rook being captured on, its starting square
+        match (pos.file.get(), pos.rank.get()) {
+            (4, 0) => { self.white_kingside = false; self.white_queenside = false; }
+            (7, 0) => self.white_kingside = false,
+            (0, 0) => self.white_queenside = false,
+            (4, 7) => { self.black_kingside = false; self.black_queenside = false; }
+            (7, 7) => self.black_kingside = false,
+            (0, 7) => self.black_queenside = false,
+            _ => {}
+        }
+    }
+}
+
+impl fmt::Display for CastlingRights {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+
+        let mut field = String::new();
+
+        if self.white_kingside { field.push('K'); }
+        if self.white_queenside { field.push('Q'); }
+        if self.black_kingside { field.push('k'); }
+        if self.black_queenside { field.push('q'); }
+
+        if field.is_empty() {
+            field.push('-');
+        }
+
+        write!(f, "{}", field)
+    }
+}
+
+
+impl PositionInfo {
+
+    pub fn starting() -> Self {
+        PositionInfo {
+            active_player: PlayerColor::White,
+            castling: CastlingRights { white_kingside: true, white_queenside: true, black_kingside: true, black_queenside: true },
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+        }
+    }
+
+    // the position info after mv is played, board is the board *before* the move
+    pub fn after_move(&self, board: &Board, mv: ChessMove) -> PositionInfo {
+
+        let from = mv.piece_movement.from;
+        let to = mv.piece_movement.to;
+
+        let is_pawn = match board.get_piece(from) {
+            Some(piece) => piece.piece_type == PieceType::Pawn,
+            None => false,
+        };
+
+        // a pawn moving diagonally onto an empty square captures en passant
+        let is_capture = board.get_piece(to).is_some() || (is_pawn && from.file.get() != to.file.get());
+
+        let mut castling = self.castling;
+        castling.remove_for_square(from);
+        castling.remove_for_square(to);
+
+        let en_passant = if is_pawn && from.rank.get().abs_diff(to.rank.get()) == 2 {
+            Some(square(from.file.get(), (from.rank.get() + to.rank.get()) / 2))
+        } else {
+            None
+        };
+
+        PositionInfo {
+            active_player: opponent_color(self.active_player),
+            castling,
+            en_passant,
+            halfmove_clock: if is_pawn || is_capture { 0 } else { self.halfmove_clock + 1 },
+            fullmove_number: match self.active_player {
+                PlayerColor::White => self.fullmove_number,
+                PlayerColor::Black => self.fullmove_number + 1,
+            },
+        }
+    }
+}
+
+
+pub fn placement(board: &Board) -> String {
+
+    let mut fen_board = String::new();
+
+    for rank in (0..8).rev() { // eighth rank first
+
+        let mut empty_squares = 0;
+
+        for file in 0..8 { // a8, b8, ..., h8 etc.
+
+            match board.get_piece(square(file, rank)) {
+                Some(piece) => {
+                    if empty_squares != 0 {
+                        fen_board += &empty_squares.to_string();
+                        empty_squares = 0;
+                    }
+                    fen_board.push(piece_char(piece));
+                }
+                None => empty_squares += 1,
+            }
+        }
+
+        if empty_squares != 0 {
+            fen_board += &empty_squares.to_string();
+        }
+
+        if rank > 0 {
+            fen_board.push('/');
+        }
+    }
+
+    fen_board
+}
+
+pub fn write(board: &Board, info: &PositionInfo) -> String {
+
+    let side = match info.active_player {
+        PlayerColor::White => "w",
+        PlayerColor::Black => "b",
+    };
+
+    let en_passant = match info.en_passant {
+        Some(pos) => square_name(pos),
+        None => "-".to_string(),
+    };
+
+    format!("{} {} {} {} {} {}", placement(board), side, info.castling, en_passant, info.halfmove_clock, info.fullmove_number)
+}
+
+fn parse_placement(field: &str) -> Result<Board, FenError> {
+
+    let mut board = empty_board();
+
+    let rows: Vec<&str> = field.split('/').collect();
+
+    if rows.len() != 8 {
+        return Err(FenError::InvalidPlacement(format!("expected 8 ranks, got {}", rows.len())));
+    }
+
+    for (i, row) in rows.iter().enumerate() {
+
+        let rank = 7 - i as u8; // eighth rank first
+        let mut file: u8 = 0;
+        let mut previous_was_digit = false;
+
+        for c in row.chars() {
+
+            if file >= 8 {
+                return Err(FenError::InvalidPlacement(format!("rank {} has more than 8 squares", rank + 1)));
+            }
+
+            if let Some(empty_squares) = c.to_digit(10) {
+
+                if empty_squares == 0 || empty_squares > 8 - file as u32 || previous_was_digit {
+                    return Err(FenError::InvalidPlacement(format!("bad empty square count '{}' on rank {}", c, rank + 1)));
+                }
+                file += empty_squares as u8;
+                previous_was_digit = true;
+                continue;
+            }
+
+            previous_was_digit = false;
+
+            let piece = char_piece(c).ok_or(FenError::InvalidPlacement(format!("unknown piece '{}'", c)))?;
+
+            if piece.piece_type == PieceType::Pawn && (rank == 0 || rank == 7) {
+                return Err(FenError::InvalidPlacement(format!("pawn on rank {}", rank + 1)));
+            }
+
+            board.set_piece(square(file, rank), Some(piece));
+            file += 1;
+        }
+
+        if file != 8 {
+            return Err(FenError::InvalidPlacement(format!("rank {} has {} squares", rank + 1, file)));
+        }
+    }
+
+    // exactly one king per side
+    for player in [PlayerColor::White, PlayerColor::Black] {
+
+        let kings = (0..64)
+            .filter(|i| is_piece(&board, square(i % 8, i / 8), PieceType::King, player))
+            .count();
+
+        if kings != 1 {
+            return Err(FenError::InvalidKings);
+        }
+    }
+
+    Ok(board)
+}
+
+fn parse_clock(field: &str) -> Result<u32, FenError> {
+    field.parse().map_err(|_| FenError::InvalidClock(field.to_string()))
+}
+
+// accepts all six fields, the first four (clocks default to "0 1") or just the
+// piece placement (White to move, castling rights taken from the king and rook squares)
+pub fn parse(fen: &str) -> Result<(Board, PositionInfo), FenError> {
+
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+
+    if fields.is_empty() {
+        return Err(FenError::WrongFieldCount(0));
+    }
+
+    let board = parse_placement(fields[0])?;
+
+    if fields.len() == 1 {
+        let mut info = PositionInfo::starting();
+        info.castling = CastlingRights::from_board(&board);
+        return Ok((board, info));
+    }
+
+    if fields.len() != 4 && fields.len() != 6 {
+        return Err(FenError::WrongFieldCount(fields.len()));
+    }
+
+    let active_player = match fields[1] {
+        "w" => PlayerColor::White,
+        "b" => PlayerColor::Black,
+        side => return Err(FenError::InvalidSideToMove(side.to_string())),
+    };
+
+    let castling = CastlingRights::parse(fields[2], &board)?;
+
+    let en_passant = match fields[3] {
+        "-" => None,
+        name => {
+            // the square behind a pawn that just moved two steps
+            let expected_rank = match active_player {
+                PlayerColor::White => 5,
+                PlayerColor::Black => 2,
+            };
+
+            match parse_square(name) {
+                Some(pos) if pos.rank.get() == expected_rank => Some(pos),
+                _ => return Err(FenError::InvalidEnPassant(name.to_string())),
+            }
+        }
+    };
+
+    let (halfmove_clock, fullmove_number) = if fields.len() == 6 {
+        (parse_clock(fields[4])?, parse_clock(fields[5])?)
+    } else {
+        (0, 1)
+    };
+
+    if fullmove_number == 0 {
+        return Err(FenError::InvalidClock(fields[5].to_string()));
+    }
+
+    Ok((board, PositionInfo { active_player, castling, en_passant, halfmove_clock, fullmove_number }))
+}
+
+pub fn default_fen() -> String {
+    write(&Board::default_board(), &PositionInfo::starting())
+}
+
+pub fn new_game(fen: &str) -> Result<(ChessGame, PositionInfo), FenError> {
+
+    let (board, info) = parse(fen)?;
+
+    // ChessGame::new always starts with White to move
+    if info.active_player != PlayerColor::White {
+        return Err(FenError::BlackToMove);
+    }
+
+    // it also knows nothing of earlier moves: every king and rook on its starting square may castle
+    let rights = CastlingRights::from_board(&board);
+    if info.castling != rights {
+        return Err(FenError::UnsupportedCastling(rights));
+    }
+
+    // and there is no pawn that just moved two steps, an en passant target is fine only while no pawn could take
+    if let Some(target) = info.en_passant {
+
+        let passed_rank = target.rank.get() - 1;
+        let takers = [target.file.get().checked_sub(1), Some(target.file.get() + 1).filter(|&file| file < 8)];
+
+        if takers.into_iter().flatten().any(|file| is_piece(&board, square(file, passed_rank), PieceType::Pawn, PlayerColor::White)) {
+            return Err(FenError::UnsupportedEnPassant(square_name(target)));
+        }
+    }
+
+    Ok((ChessGame::new(board), info))
+}
+
+// compares our position with the one the opponent sent. some clients only send
+// the piece placement, in which case that is all we can compare.
+pub fn same_position(ours: &str, theirs: &str) -> bool {
+
+    if theirs.split_whitespace().count() == 1 {
+        return ours.split_whitespace().next() == Some(theirs);
+    }
+
+    ours == theirs
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    fn round_trip(fen: &str) -> String {
+        let (board, info) = parse(fen).unwrap();
+        write(&board, &info)
+    }
+
+    fn play(fen: &str, moves: &[&str]) -> String {
+
+        let (mut board, mut info) = parse(fen).unwrap();
+
+        for mv in moves {
+
+            let from = parse_square(&mv[0..2]).unwrap();
+            let to = parse_square(&mv[2..4]).unwrap();
+            let chess_move = ChessMove {
+                piece_movement: leben_chess::moves::PieceMovement { from, to },
+                promotion: None,
+            };
+
+            info = info.after_move(&board, chess_move);
+
+            // enough of a move for these tests: no castling rook or en passant capture
+            let piece = board.get_piece(from);
+            board.set_piece(from, None);
+            board.set_piece(to, piece);
+        }
+
+        write(&board, &info)
+    }
+
+    #[test]
+    fn well_known_positions_round_trip() {
+
+        let fens = [
+            START_FEN,
+            KIWIPETE,
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            // en passant available for White / for Black
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "rnbqkbnr/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/RNBQKBNR b KQkq d3 0 2",
+            // partial castling rights
+            "r3k2r/8/8/8/8/8/8/R3K2R b Kq - 12 40",
+            "4k3/8/8/8/8/8/8/4K3 w - - 99 120",
+        ];
+
+        for fen in fens {
+            assert_eq!(round_trip(fen), fen);
+        }
+    }
+
+    #[test]
+    fn optional_fields_get_defaults() {
+
+        assert_eq!(round_trip("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR"), START_FEN);
+        assert_eq!(round_trip("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -"), START_FEN);
+
+        // placement only: castling rights follow the pieces on their home squares
+        assert_eq!(round_trip("r3k3/8/8/8/8/8/8/4K2R"), "r3k3/8/8/8/8/8/8/4K2R w Kq - 0 1");
+        assert_eq!(round_trip("4k3/8/8/8/8/8/8/3K3R"), "4k3/8/8/8/8/8/8/3K3R w - - 0 1");
+    }
+
+    #[test]
+    fn rejects_invalid_fens() {
+
+        let cases = [
+            ("", FenError::WrongFieldCount(0)),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq", FenError::WrongFieldCount(3)),
+            ("rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::InvalidPlacement("expected 8 ranks, got 7".to_string())),
+            ("rnbqkbnr/ppppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::InvalidPlacement("rank 7 has more than 8 squares".to_string())),
+            ("rnbqkbnr/ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::InvalidPlacement("rank 7 has 7 squares".to_string())),
+            ("rnbqkbnr/pppppppp/44/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::InvalidPlacement("bad empty square count '4' on rank 6".to_string())),
+            ("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::InvalidPlacement("bad empty square count '9' on rank 6".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1", FenError::InvalidPlacement("unknown piece 'X'".to_string())),
+            ("rnbqkbnp/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::InvalidPlacement("pawn on rank 8".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQQBNR w kq - 0 1", FenError::InvalidKings),
+            ("rnbqkbnr/pppppppp/8/8/8/4K3/PPPPPPPP/RNBQKBNR w kq - 0 1", FenError::InvalidKings),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1", FenError::InvalidSideToMove("x".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e5 0 1", FenError::InvalidEnPassant("e5".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e3 0 1", FenError::InvalidEnPassant("e3".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq i6 0 1", FenError::InvalidEnPassant("i6".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1", FenError::InvalidClock("x".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 0", FenError::InvalidClock("0".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - -1 1", FenError::InvalidClock("-1".to_string())),
+        ];
+
+        for (fen, error) in cases {
+            assert_eq!(parse(fen).err(), Some(error), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_castling_rights() {
+
+        let fens = [
+            "r3k2r/8/8/8/8/8/8/R3K2R w KK - 0 1",
+            "r3k2r/8/8/8/8/8/8/R3K2R w QK - 0 1",
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQx - 0 1",
+            // rook or king not on its starting square
+            "r3k2r/8/8/8/8/8/8/R3K1R1 w K - 0 1",
+            "r3k2r/8/8/8/8/8/8/R4K1R w Q - 0 1",
+            "1r2k2r/8/8/8/8/8/8/R3K2R w q - 0 1",
+        ];
+
+        for fen in fens {
+            assert!(matches!(parse(fen), Err(FenError::InvalidCastling(_))), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn tracks_en_passant_and_clocks() {
+
+        assert_eq!(play(START_FEN, &["e2e4"]), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        assert_eq!(play(START_FEN, &["e2e4", "e7e5"]), "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2");
+
+        // knight moves count towards the fifty-move rule, a single pawn step resets it
+        assert_eq!(play(START_FEN, &["g1f3", "g8f6", "f3g1"]), "rnbqkb1r/pppppppp/5n2/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 3 2");
+        assert_eq!(play(START_FEN, &["g1f3", "g8f6", "e2e3"]), "rnbqkb1r/pppppppp/5n2/8/8/4PN2/PPPP1PPP/RNBQKB1R b KQkq - 0 2");
+    }
+
+    #[test]
+    fn tracks_castling_rights() {
+
+        // king move loses both rights, rook move only its own side
+        assert_eq!(play(KIWIPETE, &["e1d1"]), "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R2K3R b kq - 1 1");
+        assert_eq!(play(KIWIPETE, &["a1b1", "h8g8"]), "r3k1r1/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/1R2K2R w Kq - 2 2");
+
+        // capturing a rook on its starting square removes the opponent's right
+        let fen = "r3k2r/8/8/8/8/8/6B1/R3K2R w KQkq - 0 1";
+        assert_eq!(play(fen, &["g2a8"]), "B3k2r/8/8/8/8/8/8/R3K2R b KQk - 0 1");
+    }
+
+    #[test]
+    fn black_to_move_cannot_start_a_game() {
+        assert!(matches!(new_game("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"), Err(FenError::BlackToMove)));
+    }
+
+    #[test]
+    fn new_games_need_castling_and_en_passant_the_chess_lib_can_play() {
+
+        let missing_right = new_game("r3k2r/8/8/8/8/8/8/R3K2R w Kkq - 0 1").err();
+        assert_eq!(missing_right, Some(FenError::UnsupportedCastling(parse("r3k2r/8/8/8/8/8/8/R3K2R").unwrap().1.castling)));
+        assert!(new_game("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").is_ok());
+        assert!(new_game("r3k2r/8/8/8/8/8/8/1R2K1R1 w kq - 0 1").is_ok());
+
+        // e5 could take on d6, but not as the first move of a new game
+        assert_eq!(new_game("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").err(), Some(FenError::UnsupportedEnPassant("d6".to_string())));
+        assert!(new_game("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2").is_ok());
+    }
+
+    #[test]
+    fn compares_placement_only_fens() {
+
+        let full = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+
+        assert!(same_position(full, full));
+        assert!(same_position(full, "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR"));
+        assert!(!same_position(full, "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"));
+        assert!(!same_position(full, "rnbqkbnr/pppppppp/8/8/8/4P3/PPPP1PPP/RNBQKBNR"));
+    }
+
+}
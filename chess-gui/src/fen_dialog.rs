@@ -0,0 +1,89 @@
+// dialog for starting a local game from a FEN, opened with Ctrl+V on the board
+//
+// the clipboard content is pasted in right away, after that the text can be edited
+// by typing, Backspace and Ctrl+V. Enter starts the game, Escape closes the dialog.
+
+use ggez::graphics::{self, Canvas, Color, DrawParam};
+use ggez::{Context, GameResult};
+use ggez::glam::*;
+
+use crate::{WIDTH, SQUARE_SIZE};
+
+
+pub struct FenDialog {
+    pub text: String,
+    pub error: Option<String>,
+}
+
+impl FenDialog {
+
+    pub fn new() -> Self {
+
+        let mut dialog = FenDialog { text: String::new(), error: None };
+        dialog.paste();
+        dialog
+    }
+
+    pub fn paste(&mut self) {
+
+        let clipboard_text = arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_text());
+
+        match clipboard_text {
+            Ok(text) => {
+                // FENs copied from websites often come with a trailing newline
+                self.text += text.trim();
+                self.error = None;
+            }
+            Err(e) => self.error = Some(format!("Could not read the clipboard: {}", e)),
+        }
+    }
+
+    pub fn type_char(&mut self, c: char) {
+
+        if !c.is_control() {
+            self.text.push(c);
+            self.error = None;
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        self.text.pop();
+        self.error = None;
+    }
+
+    pub fn draw(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+
+        let x = SQUARE_SIZE/2.0;
+        let y = SQUARE_SIZE*2.5;
+        let width = WIDTH - SQUARE_SIZE;
+
+        let overlay = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(0.0, 0.0, width, SQUARE_SIZE*3.0),
+            Color::from_rgba(0, 0, 60, 220),
+        )?;
+        canvas.draw(&overlay, Vec2::new(x, y));
+
+        let mut title = graphics::Text::new("Start from FEN");
+        title.set_scale(48.0);
+        canvas.draw(&title, DrawParam::default().dest([x + 24.0, y + 24.0]));
+
+        let mut fen_text = graphics::Text::new(format!("{}_", self.text));
+        fen_text.set_scale(32.0);
+        fen_text.set_bounds(Vec2::new(width - 48.0, SQUARE_SIZE));
+        canvas.draw(&fen_text, DrawParam::default().dest([x + 24.0, y + SQUARE_SIZE]));
+
+        let (hint, color) = match &self.error {
+            Some(e) => (e.clone(), Color::from_rgb(255, 100, 100)),
+            None => ("Ctrl+V to paste, Enter to start, Esc to cancel".to_string(), Color::WHITE),
+        };
+
+        let mut hint_text = graphics::Text::new(hint);
+        hint_text.set_scale(28.0);
+        canvas.draw(&hint_text, DrawParam::default().dest([x + 24.0, y + SQUARE_SIZE*2.25]).color(color));
+
+        Ok(())
+    }
+
+}
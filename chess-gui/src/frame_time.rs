@@ -0,0 +1,74 @@
+// how long drawing a frame takes, averaged over the last few frames
+//
+// shown in the corner of the window with F3, to see what drawing changes cost
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use ggez::graphics::{self, Canvas, Color, DrawParam};
+use ggez::glam::*;
+
+
+const FRAMES: usize = 60;
+
+#[derive(Default)]
+pub struct FrameTimer {
+    times: VecDeque<Duration>, // most recent last
+}
+
+impl FrameTimer {
+
+    pub fn record(&mut self, time: Duration) {
+
+        if self.times.len() == FRAMES {
+            self.times.pop_front();
+        }
+        self.times.push_back(time);
+    }
+
+    pub fn average(&self) -> Option<Duration> {
+
+        if self.times.is_empty() {
+            return None;
+        }
+
+        Some(self.times.iter().sum::<Duration>() / self.times.len() as u32)
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas, dest: Vec2) {
+
+        let Some(average) = self.average() else {
+            return;
+        };
+
+        let mut text = graphics::Text::new(format!("draw: {:.2} ms", average.as_secs_f64()*1000.0));
+        text.set_scale(16.0);
+        canvas.draw(&text, DrawParam::default().dest(dest).color(Color::from_rgb(0, 200, 0)));
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn averages_the_last_frames() {
+
+        let mut timer = FrameTimer::default();
+        assert!(timer.average().is_none());
+
+        timer.record(Duration::from_millis(10));
+        timer.record(Duration::from_millis(20));
+        assert_eq!(timer.average(), Some(Duration::from_millis(15)));
+
+        // old frames drop out
+        for _ in 0..FRAMES {
+            timer.record(Duration::from_millis(2));
+        }
+        assert_eq!(timer.average(), Some(Duration::from_millis(2)));
+    }
+
+}
@@ -0,0 +1,337 @@
+// what the mouse is doing on the board: selecting, dragging, and picking a promotion piece
+//
+//   Idle --press on a piece that can move--> Dragging
+//   Dragging --release on the same square--> PieceSelected (or Idle, if it already was selected)
+//   Dragging --release on a target--> Idle + move, or AwaitingPromotion
+//   Dragging --release anywhere else--> PieceSelected, the piece snaps back
+//   PieceSelected --press on the selected piece--> Dragging, releasing it there deselects
+//   PieceSelected --press on a target--> Idle + move, or AwaitingPromotion
+//   PieceSelected --press on another piece that can move--> Dragging that one
+//   PieceSelected --press anywhere else--> Idle
+//   AwaitingPromotion --choose--> Idle + move, --cancel--> Idle
+//
+// the chess rules are asked through the Rules trait, so this can be tested without a game or a window
+
+use ggez::glam::*;
+use leben_chess::board::board_pos::BoardPosition;
+use leben_chess::chess::ChessGame;
+use leben_chess::moves::{ChessMove, PieceMovement, PromotionType};
+
+
+pub trait Rules {
+    fn has_moves(&self, from: BoardPosition) -> bool;
+    fn can_move(&self, from: BoardPosition, to: BoardPosition) -> bool;
+    fn is_promotion(&self, from: BoardPosition) -> bool;
+}
+
+impl Rules for ChessGame {
+
+    fn has_moves(&self, from: BoardPosition) -> bool {
+        !self.available_moves(from).is_all_zeros()
+    }
+
+    fn can_move(&self, from: BoardPosition, to: BoardPosition) -> bool {
+        self.available_moves(from).get(to)
+    }
+
+    fn is_promotion(&self, from: BoardPosition) -> bool {
+        self.expects_promotion_move(from)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum InputState {
+    Idle,
+    PieceSelected { from: BoardPosition },
+    // cursor: in window pixels. was_selected: releasing it on the spot deselects it
+    Dragging { from: BoardPosition, cursor: Vec2, was_selected: bool },
+    AwaitingPromotion { from: BoardPosition, to: BoardPosition },
+}
+
+impl InputState {
+
+    // the square whose legal moves are shown
+    pub fn selected(&self) -> Option<BoardPosition> {
+
+        match *self {
+            InputState::Idle => None,
+            InputState::PieceSelected { from } => Some(from),
+            InputState::Dragging { from, .. } => Some(from),
+            InputState::AwaitingPromotion { from, .. } => Some(from),
+        }
+    }
+
+    // the lifted piece and where it is
+    pub fn dragged(&self) -> Option<(BoardPosition, Vec2)> {
+
+        match *self {
+            InputState::Dragging { from, cursor, .. } => Some((from, cursor)),
+            _ => None,
+        }
+    }
+
+    pub fn promotion(&self) -> Option<(BoardPosition, BoardPosition)> {
+
+        match *self {
+            InputState::AwaitingPromotion { from, to } => Some((from, to)),
+            _ => None,
+        }
+    }
+
+    // mouse button pressed on a board square
+    pub fn press(&mut self, rules: &impl Rules, square: BoardPosition, cursor: Vec2) -> Option<ChessMove> {
+
+        match *self {
+
+            InputState::PieceSelected { from } if square == from => {
+                *self = InputState::Dragging { from, cursor, was_selected: true };
+                None
+            }
+
+            InputState::PieceSelected { from } if rules.can_move(from, square) => self.finish(rules, from, square),
+
+            // the promotion picker takes these clicks
+            InputState::AwaitingPromotion { .. } => None,
+
+            // Idle, or a click somewhere the selected piece can't go: maybe another piece is picked
+            _ => {
+                *self = if rules.has_moves(square) {
+                    InputState::Dragging { from: square, cursor, was_selected: false }
+                } else {
+                    InputState::Idle
+                };
+                None
+            }
+        }
+    }
+
+    pub fn motion(&mut self, to: Vec2) {
+
+        if let InputState::Dragging { cursor, .. } = self {
+            *cursor = to;
+        }
+    }
+
+    // mouse button released, square: None outside of the board
+    pub fn release(&mut self, rules: &impl Rules, square: Option<BoardPosition>) -> Option<ChessMove> {
+
+        let InputState::Dragging { from, was_selected, .. } = *self else {
+            return None;
+        };
+
+        match square {
+
+            // a click: the first one selects, the second one deselects
+            Some(to) if to == from => {
+                *self = if was_selected { InputState::Idle } else { InputState::PieceSelected { from } };
+                None
+            }
+
+            Some(to) if rules.can_move(from, to) => self.finish(rules, from, to),
+
+            // the piece snaps back and stays selected
+            _ => {
+                *self = InputState::PieceSelected { from };
+                None
+            }
+        }
+    }
+
+    pub fn choose_promotion(&mut self, promotion: PromotionType) -> Option<ChessMove> {
+
+        let InputState::AwaitingPromotion { from, to } = *self else {
+            return None;
+        };
+
+        *self = InputState::Idle;
+        Some(ChessMove { piece_movement: PieceMovement { from, to }, promotion: Some(promotion) })
+    }
+
+    pub fn cancel(&mut self) {
+        *self = InputState::Idle;
+    }
+
+    fn finish(&mut self, rules: &impl Rules, from: BoardPosition, to: BoardPosition) -> Option<ChessMove> {
+
+        if rules.is_promotion(from) {
+            *self = InputState::AwaitingPromotion { from, to };
+            return None;
+        }
+
+        *self = InputState::Idle;
+        Some(ChessMove { piece_movement: PieceMovement { from, to }, promotion: None })
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::fen::parse_square;
+
+    // a few hand-picked moves instead of a whole game
+    struct FakeRules {
+        moves: Vec<(&'static str, &'static str)>,
+        promotion_from: Option<&'static str>,
+    }
+
+    impl Rules for FakeRules {
+
+        fn has_moves(&self, from: BoardPosition) -> bool {
+            self.moves.iter().any(|(f, _)| sq(f) == from)
+        }
+
+        fn can_move(&self, from: BoardPosition, to: BoardPosition) -> bool {
+            self.moves.iter().any(|(f, t)| sq(f) == from && sq(t) == to)
+        }
+
+        fn is_promotion(&self, from: BoardPosition) -> bool {
+            self.promotion_from.is_some_and(|square| sq(square) == from)
+        }
+    }
+
+    fn sq(name: &str) -> BoardPosition {
+        parse_square(name).unwrap()
+    }
+
+    fn rules() -> FakeRules {
+        FakeRules {
+            moves: vec![("e2", "e4"), ("e2", "e3"), ("g1", "f3"), ("a7", "a8")],
+            promotion_from: Some("a7"),
+        }
+    }
+
+    fn click(state: &mut InputState, rules: &FakeRules, square: &str) -> Option<ChessMove> {
+        state.press(rules, sq(square), Vec2::ZERO)
+            .or_else(|| state.release(rules, Some(sq(square))))
+    }
+
+    fn is_move(mv: Option<ChessMove>, from: &str, to: &str) -> bool {
+        mv.is_some_and(|mv| mv.piece_movement.from == sq(from) && mv.piece_movement.to == sq(to))
+    }
+
+    #[test]
+    fn click_selects_and_click_on_target_moves() {
+
+        let rules = rules();
+        let mut state = InputState::Idle;
+
+        assert!(click(&mut state, &rules, "e2").is_none());
+        assert!(matches!(state, InputState::PieceSelected { from } if from == sq("e2")));
+
+        assert!(is_move(click(&mut state, &rules, "e4"), "e2", "e4"));
+        assert!(matches!(state, InputState::Idle));
+    }
+
+    #[test]
+    fn pieces_without_moves_are_not_selected() {
+
+        let rules = rules();
+        let mut state = InputState::Idle;
+
+        click(&mut state, &rules, "d1");
+        assert!(matches!(state, InputState::Idle));
+    }
+
+    #[test]
+    fn second_click_deselects() {
+
+        let rules = rules();
+        let mut state = InputState::Idle;
+
+        click(&mut state, &rules, "e2");
+        click(&mut state, &rules, "e2");
+        assert!(matches!(state, InputState::Idle));
+    }
+
+    #[test]
+    fn clicking_another_piece_reselects() {
+
+        let rules = rules();
+        let mut state = InputState::Idle;
+
+        click(&mut state, &rules, "e2");
+        assert!(click(&mut state, &rules, "g1").is_none());
+        assert!(matches!(state, InputState::PieceSelected { from } if from == sq("g1")));
+
+        assert!(is_move(click(&mut state, &rules, "f3"), "g1", "f3"));
+    }
+
+    #[test]
+    fn clicking_an_illegal_target_deselects() {
+
+        let rules = rules();
+        let mut state = InputState::Idle;
+
+        click(&mut state, &rules, "e2");
+        assert!(click(&mut state, &rules, "e5").is_none());
+        assert!(matches!(state, InputState::Idle));
+    }
+
+    #[test]
+    fn drag_and_drop_moves() {
+
+        let rules = rules();
+        let mut state = InputState::Idle;
+
+        state.press(&rules, sq("e2"), Vec2::new(10.0, 10.0));
+        state.motion(Vec2::new(50.0, 60.0));
+        assert!(state.dragged() == Some((sq("e2"), Vec2::new(50.0, 60.0))));
+
+        assert!(is_move(state.release(&rules, Some(sq("e3"))), "e2", "e3"));
+        assert!(matches!(state, InputState::Idle));
+    }
+
+    #[test]
+    fn illegal_drop_snaps_back() {
+
+        let rules = rules();
+        let mut state = InputState::Idle;
+
+        state.press(&rules, sq("e2"), Vec2::ZERO);
+        assert!(state.release(&rules, Some(sq("e6"))).is_none());
+        assert!(matches!(state, InputState::PieceSelected { from } if from == sq("e2")));
+
+        state.press(&rules, sq("e2"), Vec2::ZERO);
+        assert!(state.release(&rules, None).is_none());
+        assert!(matches!(state, InputState::PieceSelected { from } if from == sq("e2")));
+    }
+
+    #[test]
+    fn promotion_waits_for_the_piece() {
+
+        let rules = rules();
+        let mut state = InputState::Idle;
+
+        click(&mut state, &rules, "a7");
+        assert!(click(&mut state, &rules, "a8").is_none());
+        assert!(state.promotion() == Some((sq("a7"), sq("a8"))));
+
+        // clicks on the board don't get through while the picker is open
+        assert!(state.press(&rules, sq("e2"), Vec2::ZERO).is_none());
+        assert!(state.promotion() == Some((sq("a7"), sq("a8"))));
+
+        let mv = state.choose_promotion(PromotionType::Queen).unwrap();
+        assert!(matches!(mv.promotion, Some(PromotionType::Queen)));
+        assert!(is_move(Some(mv), "a7", "a8"));
+        assert!(matches!(state, InputState::Idle));
+    }
+
+    #[test]
+    fn promotion_can_be_cancelled() {
+
+        let rules = rules();
+        let mut state = InputState::Idle;
+
+        state.press(&rules, sq("a7"), Vec2::ZERO);
+        state.release(&rules, Some(sq("a8")));
+        assert!(state.promotion().is_some());
+
+        state.cancel();
+        assert!(matches!(state, InputState::Idle));
+        assert!(state.choose_promotion(PromotionType::Knight).is_none());
+    }
+
+}
@@ -0,0 +1,126 @@
+// where things go in the window, recomputed whenever it's resized
+//
+// the board is as large as fits and centered. When the window is wide enough, the side panel
+// (clocks and buttons) goes to the right of the board, otherwise there is no side panel.
+// everything is in window pixels, except for dialogs: those are laid out in a fixed
+// WIDTH x HEIGHT coordinate system that covers the board, see overlay_coordinates()
+
+use ggez::graphics::Rect;
+use ggez::glam::*;
+
+use crate::{WIDTH, HEIGHT};
+
+
+// side panel width, per board width
+pub const PANEL_RATIO: f32 = 3.0/8.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Layout {
+    pub window: Rect,
+    pub board: Rect,
+    pub panel: Option<Rect>,
+}
+
+impl Layout {
+
+    pub fn new(width: f32, height: f32) -> Self {
+
+        let window = Rect::new(0.0, 0.0, width, height);
+
+        // at least half of a panel's width to spare
+        if width >= height*(1.0 + PANEL_RATIO/2.0) {
+
+            let size = height.min(width / (1.0 + PANEL_RATIO));
+            let panel_width = size*PANEL_RATIO;
+
+            // board and panel are centered together
+            let x = (width - size - panel_width)/2.0;
+            let y = (height - size)/2.0;
+
+            return Layout {
+                window,
+                board: Rect::new(x, y, size, size),
+                panel: Some(Rect::new(x + size, y, panel_width, size)),
+            };
+        }
+
+        let size = width.min(height);
+
+        Layout {
+            window,
+            board: Rect::new((width - size)/2.0, (height - size)/2.0, size, size),
+            panel: None,
+        }
+    }
+
+    // screen coordinates that put the WIDTH x HEIGHT dialog coordinates right on the board
+    pub fn overlay_coordinates(&self) -> Rect {
+
+        let scale = WIDTH / self.board.w;
+
+        Rect::new(-self.board.x*scale, -self.board.y*scale, self.window.w*scale, self.window.h*scale)
+    }
+
+    // window pixels -> dialog coordinates
+    pub fn overlay_point(&self, point: Vec2) -> Vec2 {
+        (point - Vec2::new(self.board.x, self.board.y)) * Vec2::new(WIDTH / self.board.w, HEIGHT / self.board.h)
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn wide_windows_get_a_side_panel() {
+
+        let layout = Layout::new(1100.0, 800.0);
+
+        assert_eq!(layout.board, Rect::new(0.0, 0.0, 800.0, 800.0));
+        assert_eq!(layout.panel, Some(Rect::new(800.0, 0.0, 300.0, 800.0)));
+    }
+
+    #[test]
+    fn board_and_panel_are_centered() {
+
+        let layout = Layout::new(1500.0, 800.0);
+
+        assert_eq!(layout.board, Rect::new(200.0, 0.0, 800.0, 800.0));
+        assert_eq!(layout.panel, Some(Rect::new(1000.0, 0.0, 300.0, 800.0)));
+
+        // not quite wide enough for a full panel: the board gets smaller
+        let layout = Layout::new(1000.0, 800.0);
+        let panel = layout.panel.unwrap();
+
+        assert!(layout.board.w < 800.0);
+        assert!(layout.board.x.abs() < 0.01);
+        assert!((panel.x + panel.w - 1000.0).abs() < 0.01);
+        assert!((panel.w - layout.board.w*PANEL_RATIO).abs() < 0.01);
+    }
+
+    #[test]
+    fn narrow_windows_only_show_the_board() {
+
+        let layout = Layout::new(800.0, 1000.0);
+
+        assert_eq!(layout.board, Rect::new(0.0, 100.0, 800.0, 800.0));
+        assert_eq!(layout.panel, None);
+    }
+
+    #[test]
+    fn overlay_coordinates_cover_the_board() {
+
+        let layout = Layout::new(1500.0, 800.0);
+
+        assert_eq!(layout.overlay_point(Vec2::new(200.0, 0.0)), Vec2::new(0.0, 0.0));
+        assert_eq!(layout.overlay_point(Vec2::new(1000.0, 800.0)), Vec2::new(WIDTH, HEIGHT));
+
+        let screen = layout.overlay_coordinates();
+        assert_eq!(screen.x, -200.0*WIDTH/800.0);
+        assert_eq!(screen.w, 1500.0*WIDTH/800.0);
+    }
+
+}
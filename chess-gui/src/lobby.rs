@@ -0,0 +1,171 @@
+// lobby: sets up the network connection in the background, so that the window
+// can show what is going on (and be closed) while we wait for the opponent
+
+use std::io;
+use std::net::TcpListener;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use ggez::graphics::{self, Canvas, Color, DrawParam, Rect};
+use ggez::{Context, GameResult};
+use ggez::glam::*;
+use leben_chess::board::piece::PlayerColor;
+
+use crate::clock::TimeControl;
+use crate::network::{NetworkPlayer, random_color};
+use crate::protocol::ProtocolMessage;
+use crate::{WIDTH, HEIGHT, SQUARE_SIZE, opponent_color};
+
+
+enum Phase {
+    // non-blocking listener, polled every update
+    Hosting { listener: TcpListener, color: PlayerColor, start_fen: String, time_control: Option<TimeControl> },
+    // connect() blocks, so it runs on its own thread and reports back here
+    // fallback: if nobody is listening, host a game from this position and with this time control instead
+    Joining { result: Receiver<io::Result<NetworkPlayer>>, fallback: Option<(String, Option<TimeControl>)> },
+    Failed(String),
+}
+
+pub struct Lobby {
+    addr: String,
+    phase: Phase,
+}
+
+impl Lobby {
+
+    pub fn host(bind: &str, color: Option<PlayerColor>, start_fen: String, time_control: Option<TimeControl>) -> Self {
+        Lobby { addr: bind.to_string(), phase: Lobby::listen(bind, color, start_fen, time_control) }
+    }
+
+    pub fn join(addr: &str) -> Self {
+        Lobby { addr: addr.to_string(), phase: Lobby::connect(addr, None) }
+    }
+
+    pub fn auto(addr: &str, start_fen: String, time_control: Option<TimeControl>) -> Self { // join if someone is listening, otherwise host
+        Lobby { addr: addr.to_string(), phase: Lobby::connect(addr, Some((start_fen, time_control))) }
+    }
+
+    fn listen(bind: &str, color: Option<PlayerColor>, start_fen: String, time_control: Option<TimeControl>) -> Phase {
+
+        let color = color.unwrap_or_else(random_color);
+
+        // better to find out now than when the opponent connects
+        let handshake = ProtocolMessage::Init { client_color: opponent_color(color), fen: start_fen.clone(), time_control };
+        if let Err(e) = handshake.encode() {
+            return Phase::Failed(format!("Can't host this game:\n{}", e));
+        }
+
+        let listener = TcpListener::bind(bind).and_then(|listener| {
+            listener.set_nonblocking(true)?;
+            Ok(listener)
+        });
+
+        match listener {
+            Ok(listener) => Phase::Hosting { listener, color, start_fen, time_control },
+            Err(e) => Phase::Failed(format!("Could not listen on {}:\n{}", bind, e)),
+        }
+    }
+
+    fn connect(addr: &str, fallback: Option<(String, Option<TimeControl>)>) -> Phase {
+
+        let (sender, result) = mpsc::channel();
+        let addr = addr.to_string();
+
+        thread::spawn(move || {
+            // if the lobby was cancelled the receiver is gone, nothing to do then
+            let _ = sender.send(NetworkPlayer::join(&addr));
+        });
+
+        Phase::Joining { result, fallback }
+    }
+
+    // returns the connected opponent once the connection is set up
+    pub fn poll(&mut self) -> Option<NetworkPlayer> {
+
+        let next_phase = match &mut self.phase {
+
+            Phase::Hosting { listener, color, start_fen, time_control } => match listener.accept() {
+
+                Ok((stream, sock_addr)) => {
+                    println!("Client connected from {}", sock_addr);
+
+                    match NetworkPlayer::accept(stream, *color, start_fen.clone(), *time_control) {
+                        Ok(network_player) => return Some(network_player),
+                        Err(e) => Phase::Failed(format!("Handshake with {} failed:\n{}", sock_addr, e)),
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return None,
+                Err(e) => Phase::Failed(format!("Could not accept connection:\n{}", e)),
+            }
+
+            Phase::Joining { result, fallback } => match result.try_recv() {
+
+                Ok(Ok(network_player)) => return Some(network_player),
+                Ok(Err(ref e)) if fallback.is_some() && e.kind() == io::ErrorKind::ConnectionRefused => {
+                    // nobody is hosting yet, so we do it
+                    let (start_fen, time_control) = fallback.take().unwrap();
+                    Lobby::listen(&self.addr, None, start_fen, time_control)
+                }
+                Ok(Err(e)) => Phase::Failed(format!("Could not connect to {}:\n{}", self.addr, e)),
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => Phase::Failed("Connection thread stopped unexpectedly".to_string()),
+            }
+
+            Phase::Failed(_) => return None,
+        };
+
+        self.phase = next_phase;
+
+        None
+    }
+
+    fn status(&self) -> String {
+
+        match &self.phase {
+            Phase::Hosting { color, .. } => format!("Waiting for opponent on {}\nYou play {:?}", self.addr, color),
+            Phase::Joining { .. } => format!("Connecting to {}...", self.addr),
+            Phase::Failed(e) => format!("Connection failed!\n{}", e),
+        }
+    }
+
+    pub fn cancel_button() -> Rect {
+        Rect::new(SQUARE_SIZE*3.0, SQUARE_SIZE*5.0, SQUARE_SIZE*2.0, SQUARE_SIZE*0.75)
+    }
+
+    pub fn draw(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+
+        let overlay = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(0.0, 0.0, WIDTH, HEIGHT),
+            Color::from_rgba(0, 0, 0, 200),
+        )?;
+        canvas.draw(&overlay, Vec2::new(0.0, 0.0));
+
+        let mut text = graphics::Text::new(self.status());
+        text.set_scale(48.0);
+        canvas.draw(&text, DrawParam::default().dest([SQUARE_SIZE, SQUARE_SIZE*3.0]));
+
+        // cancel button
+
+        let button = Lobby::cancel_button();
+
+        let button_mesh = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(0.0, 0.0, button.w, button.h),
+            Color::from_rgb(120, 0, 0),
+        )?;
+        canvas.draw(&button_mesh, Vec2::new(button.x, button.y));
+
+        let mut button_text = graphics::Text::new("Cancel");
+        button_text.set_scale(48.0);
+        let text_size = button_text.measure(ctx)?;
+        let text_x = button.x + (button.w - text_size.x)/2.0;
+        let text_y = button.y + (button.h - text_size.y)/2.0;
+        canvas.draw(&button_text, DrawParam::default().dest([text_x, text_y]));
+
+        Ok(())
+    }
+
+}
@@ -1,167 +1,279 @@
+mod animation;
+mod atlas;
+mod button;
+mod cli;
+mod clock;
+mod config;
+mod event_loop;
+mod fen;
+mod fen_dialog;
+mod frame_time;
+mod input;
+mod layout;
+mod lobby;
+mod material;
+mod move_list;
+mod network;
+mod offer;
+mod pgn;
+mod promotion;
+mod protocol;
+mod replay;
+mod san;
+mod settings;
+mod theme;
+
 // chess library imports
 
 use ggez::event::MouseButton;
+use ggez::input::keyboard::{KeyCode, KeyInput, KeyMods};
 //use ggez::winit::dpi::Position;
-use leben_chess::board::piece::{Piece, PieceType};
+use leben_chess::board::piece::PieceType;
 use leben_chess::board::Board;
 use leben_chess::board::board_pos::BoardPosition;
 use leben_chess::board::piece::PlayerColor;
-use leben_chess::chess::{ChessError, ChessGame, WinReason, GameStatus};
-use leben_chess::moves::{ChessMove, PieceMovement, PromotionType};
+use leben_chess::chess::{ChessError, ChessGame, GameStatus};
+use leben_chess::moves::ChessMove;
 
 // ggez imports
 
 //use ggez::winit::event_loop;
 use ggez::{event};
-use ggez::graphics::{self, Canvas, Color, DrawParam, Image};
+use ggez::graphics::{self, Canvas, Color, DrawParam};
 use ggez::{Context, GameResult};
 use ggez::glam::*;
 use leben_chess::util::U3;
 
-// tcp imports
-
-use std::io;
-use std::io::{Read, Write};
-use std::net::{TcpStream, TcpListener};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use animation::Animation;
+use atlas::{PieceAtlas, PieceSet};
+use clap::Parser;
+use cli::{Cli, Mode};
+use clock::{Clock, TimeControl};
+use fen::PositionInfo;
+use fen_dialog::FenDialog;
+use frame_time::FrameTimer;
+use input::InputState;
+use layout::Layout;
+use lobby::Lobby;
+use move_list::MoveList;
+use network::{NetworkPlayer, NetworkEvent};
+use offer::Offer;
+use pgn::{PgnGame, Termination};
+use promotion::PromotionPicker;
+use protocol::{ProtocolMessage, Outcome};
+use replay::Replay;
+use san::PlayedMove;
+use settings::Settings;
+use theme::{BoardTheme, Theme};
 
 
 // constants
+// dialogs and popups are laid out in a WIDTH x HEIGHT coordinate system, which is
+// stretched over the board wherever it is in the window (see layout.rs)
 const WIDTH: f32 = 1600.0;
 const HEIGHT: f32 = 1600.0;
 const SQUARE_SIZE: f32 = WIDTH/8.0;
 
 
-const ADDR: &str = "127.0.0.1:8080";
-const MSG_SIZE: usize = 128;
+fn opponent_color(color: PlayerColor) -> PlayerColor {
 
+    match color {
+        PlayerColor::White => PlayerColor::Black,
+        PlayerColor::Black => PlayerColor::White,
+    }
+}
 
-struct ChessPiece {
-    piece: Piece,
-    position: BoardPosition,
+// actions in the side panel, between the two clocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PanelButton {
+    Resign,
+    OfferDraw,
+    Abort,
 }
 
+impl PanelButton {
 
-impl ChessPiece {
+    const ALL: [PanelButton; 3] = [PanelButton::Resign, PanelButton::OfferDraw, PanelButton::Abort];
 
+    fn area(&self, panel: graphics::Rect) -> graphics::Rect {
 
-    fn filename(&self) -> &'static str{
+        let index = PanelButton::ALL.iter().position(|button| button == self).unwrap() as f32;
 
-        let piece_type = self.piece.piece_type;
-        let piece_color = self.piece.player;
+        let margin = panel.w/12.0;
+        let height = panel.w*0.15;
+        let spacing = margin/2.0;
+        let top = panel_captures_area(panel, false).y - PanelButton::ALL.len() as f32*(height + spacing);
 
-        match (piece_type, piece_color) {
-            (PieceType::Pawn, PlayerColor::White) => "/wp.png",
-            (PieceType::Knight, PlayerColor::White) => "/wN.png",
-            (PieceType::Bishop, PlayerColor::White) => "/wB.png",
-            (PieceType::Rook, PlayerColor::White) => "/wR.png",
-            (PieceType::Queen, PlayerColor::White) => "/wQ.png",
-            (PieceType::King, PlayerColor::White) => "/wK.png",
-            (PieceType::Pawn, PlayerColor::Black) => "/bp.png",
-            (PieceType::Knight, PlayerColor::Black) => "/bN.png",
-            (PieceType::Bishop, PlayerColor::Black) => "/bB.png",
-            (PieceType::Rook, PlayerColor::Black) => "/bR.png",
-            (PieceType::Queen, PlayerColor::Black) => "/bQ.png",
-            (PieceType::King, PlayerColor::Black) => "/bK.png",
+        graphics::Rect::new(panel.x + margin, top + index*(height + spacing), panel.w - 2.0*margin, height)
+    }
+
+    fn label(&self) -> &'static str {
 
+        match self {
+            PanelButton::Resign => "Resign (Ctrl+R)",
+            PanelButton::OfferDraw => "Offer draw (Ctrl+D)",
+            PanelButton::Abort => "Abort (Ctrl+A)",
         }
     }
 
-    fn draw(&self, ctx: &mut Context, canvas: &mut graphics::Canvas, square_size: f32) -> GameResult {
-
-        // draw image of piece on square self.position
+    fn at(panel: graphics::Rect, position: Vec2) -> Option<PanelButton> {
+        PanelButton::ALL.into_iter().find(|button| button.area(panel).contains(position))
+    }
+}
 
-        let image_path = Some(self.filename()).unwrap();
-        let piece_image = Image::from_path(ctx, image_path)?;
-        
+// side panel, from the top: a clock and what that side captured, the move list,
+// the buttons, and what the other side captured and its clock
+fn panel_clock_area(panel: graphics::Rect, top: bool) -> graphics::Rect {
 
-        // calc position of square
-        let (col, row): (u8, u8) = self.position.into();
+    let margin = panel.w/12.0;
+    let height = panel.w*0.25;
+    let y = if top { panel.y + margin } else { panel.y + panel.h - margin - height };
 
-        let x = col as f32 * square_size;
-        let y = row as f32 * square_size;
+    graphics::Rect::new(panel.x + margin, y, panel.w - 2.0*margin, height)
+}
 
-        
-        let scale = Vec2::new(square_size / piece_image.width() as f32, square_size / piece_image.height() as f32);
+fn panel_captures_area(panel: graphics::Rect, top: bool) -> graphics::Rect {
 
-        canvas.draw(&piece_image, DrawParam::default()
-                            .dest(Vec2::new(x,y))
-                            .scale(scale),
-        );
+    let clock = panel_clock_area(panel, top);
+    let height = panel.w*0.1;
+    let gap = panel.w/48.0;
+    let y = if top { clock.bottom() + gap } else { clock.y - gap - height };
 
-        Ok(())
-    }
+    graphics::Rect::new(clock.x, y, clock.w, height)
 }
 
+fn move_list_area(panel: graphics::Rect) -> graphics::Rect {
 
-fn inverse_boardpos_guipos(boardpos: BoardPosition) -> BoardPosition { 
-    
-    // on the chessboard, a1 = (0,0) etc., but on the guiboard (0,0) would match square a8
-    // this function provides mapping between LERF-mapping and gui image coordinates
-    
-    let (col, row): (u8, u8) = boardpos.into();
-
-    return BoardPosition {file: U3::try_from(col).unwrap(), rank: U3::try_from(7-row).unwrap()};
+    let margin = panel.w/12.0;
+    let top = panel_captures_area(panel, true).bottom() + margin/2.0;
+    let bottom = PanelButton::ALL.iter().map(|button| button.area(panel).y).fold(f32::MAX, f32::min) - margin/2.0;
 
+    graphics::Rect::new(panel.x + margin, top, panel.w - 2.0*margin, (bottom - top).max(0.0))
 }
 
 struct ChessBoard {
 
-    square_size: f32,
+    area: graphics::Rect, // in window pixels, from the layout
+    orientation: PlayerColor, // the side at the bottom of the screen
 
 }
 
 impl ChessBoard {
 
-    fn draw(&self, ctx: &mut Context, canvas: &mut graphics::Canvas, board: &Board) -> GameResult {
+    fn inverse_boardpos_guipos(&self, boardpos: BoardPosition) -> BoardPosition { 
+    
+        // on the chessboard, a1 = (0,0) etc., but on the guiboard (0,0) would match square a8
+        // (or h1, with Black at the bottom).
+        // this function provides mapping between LERF-mapping and gui image coordinates, both ways
+    
+        let (col, row): (u8, u8) = boardpos.into();
+
+        match self.orientation {
+            PlayerColor::White => BoardPosition {file: U3::try_from(col).unwrap(), rank: U3::try_from(7-row).unwrap()},
+            PlayerColor::Black => BoardPosition {file: U3::try_from(7-col).unwrap(), rank: U3::try_from(row).unwrap()},
+        }
+    }
+
+    fn square_size(&self) -> f32 {
+        self.area.w / 8.0
+    }
+
+    // upper left corner of a square, based on board position (not gui board position)
+    fn calc_square_pos(&self, position: BoardPosition) -> Vec2 {
+
+        let (col, row): (u8, u8) = self.inverse_boardpos_guipos(position).into();
+
+        self.gui_square_pos(col, row)
+    }
+
+    fn gui_square_pos(&self, col: u8, row: u8) -> Vec2 {
+        Vec2::new(self.area.x + col as f32 * self.square_size(), self.area.y + row as f32 * self.square_size())
+    }
+
+    // the board square under a point in window pixels, None outside of the board
+    fn square_at(&self, point: Vec2) -> Option<BoardPosition> {
+
+        if !self.area.contains(point) {
+            return None;
+        }
+
+        let col = ((point.x - self.area.x) / self.square_size()).floor().min(7.0) as u8;
+        let row = ((point.y - self.area.y) / self.square_size()).floor().min(7.0) as u8;
+
+        Some(self.inverse_boardpos_guipos(fen::square(col, row)))
+    }
+
+    fn flip(&mut self) {
+        self.orientation = opponent_color(self.orientation);
+    }
+
+    fn draw(&self, ctx: &mut Context, canvas: &mut graphics::Canvas, board_theme: &BoardTheme, coordinates: bool, atlas: &mut PieceAtlas, board: &Board) -> GameResult {
 
         // draw squares
-        self.draw_squares(ctx, canvas)?;
+        self.draw_squares(ctx, canvas, board_theme)?;
+
+        if coordinates {
+            self.draw_coordinates(canvas, board_theme);
+        }
 
         // then draw pieces
+        self.draw_pieces(canvas, atlas, board, &[]);
+
+        Ok(())
+    }
+
+    // hidden: squares whose pieces are drawn somewhere else, under the cursor or on their way there
+    fn draw_pieces(&self, canvas: &mut graphics::Canvas, atlas: &mut PieceAtlas, board: &Board, hidden: &[BoardPosition]) {
+
+        let mut pieces = Vec::new();
 
         for row in 0..8 {
 
             for col in 0..8 {
 
-                if let Some(temp_piece) = Board::get_piece(board, BoardPosition::try_from((row, col)).unwrap()) {
-
-                    let guipos = inverse_boardpos_guipos(BoardPosition::try_from((row, col)).unwrap());
+                let square = BoardPosition::try_from((row, col)).unwrap();
 
-                    let gui_piece = ChessPiece {
-                        piece: temp_piece,
-                        position: guipos,
-                    };
-                    gui_piece.draw(ctx, canvas, self.square_size)?;
+                if hidden.contains(&square) {
+                    continue;
+                }
 
+                if let Some(piece) = board.get_piece(square) {
+                    pieces.push((piece, self.calc_square_pos(square)));
                 }
             }
         }
 
-
-        Ok(())
-
+        atlas.draw_pieces(canvas, pieces, self.square_size());
     }
 
+    fn draw_squares(&self, ctx: &mut Context, canvas: &mut graphics::Canvas, board_theme: &BoardTheme) -> GameResult {
 
+        if let Some(texture) = &board_theme.texture {
 
+            let scale = Vec2::new(self.area.w / texture.width() as f32, self.area.h / texture.height() as f32);
+            canvas.draw(texture, DrawParam::default().dest(Vec2::new(self.area.x, self.area.y)).scale(scale));
 
-    fn draw_squares(&self, ctx: &mut Context, canvas: &mut graphics::Canvas) -> GameResult {
+            return Ok(());
+        }
 
         let white_square = graphics::Mesh::new_rectangle(
             ctx,
             graphics::DrawMode::fill(),
-            graphics::Rect::new(0.0, 0.0, self.square_size, self.square_size),
-             Color::from_rgb(220, 220, 220),
+            graphics::Rect::new(0.0, 0.0, self.square_size(), self.square_size()),
+            board_theme.light,
         )?;
 
         let black_square = graphics::Mesh::new_rectangle(
             ctx,
             graphics::DrawMode::fill(),
-            graphics::Rect::new(0.0, 0.0, self.square_size, self.square_size),
-            Color::from_rgb(50, 50, 50),
+            graphics::Rect::new(0.0, 0.0, self.square_size(), self.square_size()),
+            board_theme.dark,
         )?;
 
-        // (0,0) is upper left corner, (0, 1400) bottom left, etc.
+        // gui (0,0) is the upper left corner, (0,7) bottom left, etc.
         // drawing the board from upper left to bottom right.
 
         for row in 0..8 {
@@ -169,15 +281,14 @@ impl ChessBoard {
             for col in 0..8 {
 
                 // calc position of square
-                let x = col as f32 * self.square_size;
-                let y = row as f32 * self.square_size;
+                let square_pos = self.gui_square_pos(col, row);
 
                 if (row + col) % 2 == 0 {
                     // white square
-                    canvas.draw(&white_square, Vec2::new(x, y));
+                    canvas.draw(&white_square, square_pos);
                 } else {
                     // black square
-                    canvas.draw(&black_square, Vec2::new(x, y));
+                    canvas.draw(&black_square, square_pos);
 
                 }
             }
@@ -186,6 +297,35 @@ impl ChessBoard {
         Ok(())
     }
 
+    // file letters along the bottom edge and rank numbers along the left edge, inside the squares,
+    // so they follow the orientation. each is drawn in the color of the other kind of square
+    fn draw_coordinates(&self, canvas: &mut graphics::Canvas, board_theme: &BoardTheme) {
+
+        let size = self.square_size();
+        let margin = size*0.06;
+
+        let color_on = |col: u8, row: u8| if (row + col).is_multiple_of(2) { board_theme.dark } else { board_theme.light };
+
+        for i in 0..8 {
+
+            // square names are e.g. "e4": the file letter, then the rank number
+            let file = fen::square_name(self.inverse_boardpos_guipos(fen::square(i, 7)));
+            let mut text = graphics::Text::new(&file[..1]);
+            text.set_scale(size*0.2);
+            text.set_layout(graphics::TextLayout { h_align: graphics::TextAlign::End, v_align: graphics::TextAlign::End });
+
+            let corner = self.gui_square_pos(i, 7) + Vec2::new(size - margin, size - margin);
+            canvas.draw(&text, DrawParam::default().dest(corner).color(color_on(i, 7)));
+
+            let rank = fen::square_name(self.inverse_boardpos_guipos(fen::square(0, i)));
+            let mut text = graphics::Text::new(&rank[1..]);
+            text.set_scale(size*0.2);
+
+            let corner = self.gui_square_pos(0, i) + Vec2::new(margin, margin);
+            canvas.draw(&text, DrawParam::default().dest(corner).color(color_on(0, i)));
+        }
+    }
+
 
 
 }
@@ -193,747 +333,1208 @@ impl ChessBoard {
 
 struct GameState {
     game: ChessGame,
+    position: PositionInfo, // the parts of the FEN the chess lib doesn't expose
+    start_position: PositionInfo, // the same for the starting position, for the move list and the PGN
+    start_fen: Option<String>,
+    time_control: Option<TimeControl>,
+    clock: Option<Clock>,
+    moves: Vec<PlayedMove>, // every move played since the start position
+    redo_moves: Vec<PlayedMove>, // undone moves, most recently undone last
+    pgn_saved: bool,
+    layout: Layout,
     board: ChessBoard,
     gameover: bool,
-    selected_square: Option<BoardPosition>,
-    selected_target: Option<BoardPosition>,
+    input: InputState, // selection, dragging and the promotion choice
+    animation: Option<Animation>, // the last move, while its pieces are still moving
+    history: Option<(usize, ChessGame)>, // an earlier position shown read-only: (number of moves, game)
     highlight: Highlight,
     show_gameover_popup: bool,
-    promotion: bool,
+    gameover_message: Option<String>, // shown instead of the game status, e.g. on disconnect
+    forced_outcome: Option<Outcome>, // the game ended outside the chess rules, e.g. on time
+    termination: Option<Termination>, // for the PGN, in that case
     network_player: Option<NetworkPlayer>,
+    takeback_requested: Option<usize>, // we asked the opponent to go back to this many half-moves
+    offer: Option<Offer>, // the opponent asked for something, waiting for our answer
+    draw_offered: bool, // we offered a draw over the network, waiting for the answer
+    lobby: Option<Lobby>, // still waiting for the network connection
+    fen_dialog: Option<FenDialog>,
+    replay: Option<Replay>, // a PGN file is shown instead of the game
+    settings: Settings,
+    piece_sets: Vec<PieceSet>,
+    atlas: PieceAtlas, // images of the current piece set, loaded once
+    board_themes: Vec<BoardTheme>,
+    board_theme: usize, // index into board_themes
+    frame_timer: FrameTimer,
 
 }
 
 impl GameState { // set up starting position
-    fn new(ctx: &mut Context, network_game: bool, addr: &str) -> GameResult<Self> {
+    fn new(ctx: &mut Context, lobby: Option<Lobby>, start_fen: Option<String>, time_control: Option<TimeControl>) -> GameResult<Self> {
 
-        let network_player = if network_game{
-            Some(NetworkPlayer::auto(addr)?)
-        } else {
-            None
-        };
+        let (width, height) = ctx.gfx.drawable_size();
+        let layout = Layout::new(width, height);
+
+        let (game, position) = GameState::starting_position(&start_fen)
+            .map_err(|e| ggez::GameError::CustomError(format!("Invalid FEN: {}", e)))?;
+
+        // the saved piece set and board theme, or the first ones if they are gone
+        let mut settings = Settings::load(ctx);
+
+        let piece_sets = atlas::piece_sets(ctx);
+        let piece_set = piece_sets.iter()
+            .find(|set| set.id == settings.piece_set)
+            .or(piece_sets.first())
+            .ok_or_else(|| ggez::GameError::ResourceLoadError("No piece sets in resources/pieces".to_string()))?;
+        let atlas = PieceAtlas::new(ctx, piece_set)?;
+        settings.piece_set = piece_set.id.clone();
+
+        let board_themes = theme::board_themes(ctx);
+        let board_theme = board_themes.iter().position(|theme| theme.id == settings.board_theme).unwrap_or(0);
+        settings.board_theme = board_themes[board_theme].id.clone();
 
         Ok(GameState {
-            game: ChessGame::new(Board::default_board()),
+            game,
+            start_position: position.clone(),
+            position,
+            start_fen,
+            time_control,
+            clock: time_control.map(Clock::new),
+            moves: Vec::new(),
+            redo_moves: Vec::new(),
+            pgn_saved: false,
+            layout,
             board: ChessBoard { 
-                square_size: (WIDTH / 8.0),
+                area: layout.board,
+                orientation: PlayerColor::White,
             },
             gameover: false,
-            selected_square: None,
-            selected_target: None,
+            input: InputState::Idle,
+            animation: None,
+            history: None,
             highlight: Highlight::new(ctx).unwrap(),
             show_gameover_popup: false,
-            promotion: false,
-            network_player,
+            gameover_message: None,
+            forced_outcome: None,
+            termination: None,
+            network_player: None,
+            takeback_requested: None,
+            offer: None,
+            draw_offered: false,
+            lobby,
+            fen_dialog: None,
+            replay: None,
+            settings,
+            piece_sets,
+            atlas,
+            board_themes,
+            board_theme,
+            frame_timer: FrameTimer::default(),
         })
 
     }
 
-    fn reset(&mut self, ctx: &mut Context) -> GameResult {
+    fn reset(&mut self) -> GameResult {
 
-        // let network_player = if network_game{
-        //     Some(NetworkPlayer::auto(addr)?)
-        // } else {
-        //     None
-        // };
+        (self.game, self.position) = GameState::starting_position(&self.start_fen).unwrap(); // already validated in new()
+        self.start_position = self.position.clone();
+        self.moves.clear();
+        self.redo_moves.clear();
+        self.pgn_saved = false;
+        self.clock = self.time_control.map(Clock::new);
 
-        self.game = ChessGame::new(Board::default_board());
+        self.clear_selection();
+        self.animation = None;
+        self.history = None;
 
-        self.selected_square = None;
-        self.highlight.selected_square = None;
+        // a network game as Black turned the board around
+        self.board.orientation = PlayerColor::White;
 
         self.gameover = false;
         self.show_gameover_popup = false;
+        self.gameover_message = None;
+        self.forced_outcome = None;
+        self.termination = None;
 
         self.network_player = None;
-
+        self.takeback_requested = None;
+        self.offer = None;
+        self.draw_offered = false;
 
         Ok(())
     }
 
+    fn starting_position(start_fen: &Option<String>) -> Result<(ChessGame, PositionInfo), fen::FenError> {
 
+        match start_fen {
+            Some(start_fen) => fen::new_game(start_fen),
+            None => Ok((ChessGame::new(Board::default_board()), PositionInfo::starting())),
+        }
+    }
 
-}
-
- 
+    fn play_move(&mut self, mv: ChessMove) -> Result<(), ChessError> {
 
+        // every move goes through here, so that the position info and the move list stay in sync with the game
+        let next_position = self.position.after_move(self.game.board(), mv);
+        let san = san::describe(&self.game, mv);
+        let mover = self.game.active_player();
+        let animation = Animation::new(self.game.board(), mv, self.settings.animation_speed);
+        let count_before = material::PieceCount::of(self.game.board());
 
-fn calc_square_pos (position: BoardPosition) -> Vec2 { // based on board position (not gui board position)
+        self.game.do_move(mv)?;
+        self.animation = animation;
+        self.position = next_position;
 
-    let (file, rank): (u8, u8) = position.into();
+        let captured = material::captured(&count_before, &material::PieceCount::of(self.game.board()), mover);
 
-     // calc position of square
-    let x =  file as f32 * SQUARE_SIZE;
-    let y =  rank as f32 * SQUARE_SIZE;
+        if let Some(clock) = &mut self.clock {
+            clock.switch(mover);
+        }
 
-    Vec2::new(x, y)
-}
+        let san = san + san::check_suffix(&self.game);
+        self.moves.push(PlayedMove { mv, san, captured });
+        self.redo_moves.clear(); // a new move starts a new line
 
-struct Highlight {
+        Ok(())
+    }
 
-    selected_square: Option<BoardPosition>,
-    mesh: Option<graphics::Mesh>,
+    fn rebuild_game(&mut self) {
 
-}
+        // the chess lib can't take moves back, so start over and play the remaining moves again
+        (self.game, self.position) = GameState::starting_position(&self.start_fen).unwrap(); // already validated in new()
+        self.start_position = self.position.clone();
 
-impl Highlight {
+        for played in &self.moves {
+            self.position = self.position.after_move(self.game.board(), played.mv);
+            let _ = self.game.do_move(played.mv); // all of them were legal the first time
+        }
 
-    fn new(ctx: &mut Context) -> GameResult<Self> {
+        self.animation = None;
+        self.history = None;
+    }
 
-        let mesh = graphics::Mesh::new_rectangle(
-            ctx,
-            graphics::DrawMode::fill(),
-            graphics::Rect::new(0.0, 0.0, SQUARE_SIZE, SQUARE_SIZE),
-            Color::from_rgba(255, 255, 0, 128),
-        )?;
+    fn clear_selection(&mut self) {
 
-        Ok(Highlight {
-            selected_square: None,
-            mesh: Some(mesh),
-        })
+        self.input.cancel();
     }
 
+    // go back to the position after the first `ply` half-moves
+    fn take_back_to(&mut self, ply: usize) {
+
+        while self.moves.len() > ply {
+            let played = self.moves.pop().unwrap();
+            self.redo_moves.push(played);
+        }
 
-    fn draw (&self, canvas: &mut Canvas) -> GameResult{
+        self.rebuild_game();
+        self.clear_selection();
 
-       
-        if let Some(boardpos) = self.selected_square {
+        // taking back the final move reopens the game
+        self.gameover = false;
+        self.show_gameover_popup = false;
+        self.gameover_message = None;
+        self.forced_outcome = None;
+        self.termination = None;
+        self.pgn_saved = false;
+
+        // the times stay as they are, it's just someone else's turn now
+        if let Some(clock) = &mut self.clock {
+            clock.restart(self.game.active_player());
+        }
+    }
+
+    // plays one of our own moves and sends it to the opponent
+    fn submit_move(&mut self, mv: ChessMove) {
+
+        match self.play_move(mv) {
+            Ok(_) => {
+                println!("Move executed!");
 
-            if let Some(mesh) = &self.mesh {
+                // only write move if it's legal
 
-                let gui_position = inverse_boardpos_guipos(boardpos);
-                let squares_pos = calc_square_pos(gui_position);
-                canvas.draw(mesh, squares_pos);
+                let clock_times = self.clock_times();
+                if let Some(network_player) = &mut self.network_player {
 
+                    let mv_tcp = NetworkPlayer::move_message(&self.game, fen::write(self.game.board(), &self.position), mv, clock_times);
+                    NetworkPlayer::write_tcp_message(network_player, &mv_tcp);
+                }
+            }
+            Err(err) => {
+                println!("Illegal move: {:?}", err);
             }
         }
+    }
 
-        Ok(())
+    fn promotion_picker(&self) -> Option<PromotionPicker> {
+        self.input.promotion().map(|(_, to)| PromotionPicker::new(to, self.game.active_player(), &self.board))
     }
 
-}
-pub enum Role {
-    Server,
-    Client,
-}
-struct NetworkPlayer {
-    stream: TcpStream,
-    role: Role,
-    color: PlayerColor,
-}
+    fn next_piece_set(&mut self, ctx: &Context) {
 
-impl NetworkPlayer {
-    pub fn auto(addr: &str) -> io::Result<Self> { // auto determine client and server based on connection success
+        let current = self.piece_sets.iter().position(|set| set.id == self.settings.piece_set);
+        let next = &self.piece_sets[current.map_or(0, |index| (index + 1) % self.piece_sets.len())];
 
-        // Try client
-        match TcpStream::connect(addr) {
-            Ok(stream) => {
-                println!("Connected to {} as CLIENT (White)", addr);
-                stream.set_nonblocking(true)?;
-                return Ok(NetworkPlayer {stream, role: Role::Client, color: PlayerColor::White});
+        match PieceAtlas::new(ctx, next) {
+            Ok(atlas) => {
+                self.atlas = atlas;
+                self.settings.piece_set = next.id.clone();
+                self.settings.save(ctx);
+                println!("Piece set: {}", next.name);
             }
+            Err(e) => println!("Could not load piece set {}: {}", next.name, e),
+        }
+    }
 
-            Err(e) => {
-                if e.kind() != io::ErrorKind::ConnectionRefused {
-                    return Err(e);
-                }
+    fn next_board_theme(&mut self, ctx: &Context) {
 
-                // Start as server
-                let listener = TcpListener::bind(addr)?;
-                println!("Listening on {} as SERVER (Black)", addr);
-                println!("Waiting for client to connect...");
-                let (stream, sock_addr) = listener.accept()?;
-                println!("Client connected from {}", sock_addr);
-                stream.set_nonblocking(true)?;
-                return Ok(NetworkPlayer {stream, role: Role::Server, color: PlayerColor::Black});
-            }
-        }
+        self.board_theme = (self.board_theme + 1) % self.board_themes.len();
 
+        let theme = &self.board_themes[self.board_theme];
+        self.settings.board_theme = theme.id.clone();
+        self.settings.save(ctx);
+        println!("Board theme: {}", theme.name);
     }
 
-    // fixed size of buffer: 128 bytes
-    // five parts separated by ':'
-    // Message identifier: 9 characters, "ChessMOVE"
-    // Move: 5 characters, eg. A1A50 (capital letters), the last 
-    // character indicating promotion piece type
-    // Game state: "0-0" ongoing, "1-0" white won, "0-1" black won, "1-1" draw
-    // New board: FEN-notation
-    // Padding such that total num of bytes is 128
+    // remaining (White, Black) time, sent along with our moves
+    fn clock_times(&self) -> Option<(Duration, Duration)> {
+        self.clock.as_ref().map(|clock| (clock.remaining(PlayerColor::White), clock.remaining(PlayerColor::Black)))
+    }
 
-    fn read_tcp_message(&mut self) -> io::Result<Option<String>> {
+    // every way a game can end goes through here, a move half made with the mouse is dropped
+    fn end_game(&mut self) {
 
-        let mut msg_buf = [0; MSG_SIZE];
+        self.gameover = true;
+        self.input.cancel();
+    }
 
-        match self.stream.read_exact(&mut msg_buf) {
-            Ok(_) => {
-                let msg = String::from_utf8_lossy(&msg_buf).
-                    to_string();
-                return Ok(Some(msg))
-            },
-            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
-            Err(e) => panic!("IO error: {}", e),
+    fn end_on_time(&mut self, flagged: PlayerColor) {
+
+        let winner = opponent_color(flagged);
+
+        if clock::can_checkmate(self.game.board(), winner) {
+            self.forced_outcome = Some(Outcome::win(winner));
+            self.gameover_message = Some(format!("{:?} ran out of time,\n{:?} wins", flagged, winner));
+        } else {
+            self.forced_outcome = Some(Outcome::Draw);
+            self.gameover_message = Some(format!("{:?} ran out of time,\nbut {:?} can't checkmate:\ndraw", flagged, winner));
         }
 
+        self.termination = Some(Termination::TimeForfeit);
+        self.end_game();
+    }
+
+    fn undo(&mut self) {
 
+        if self.moves.is_empty() {
+            return;
+        }
+        self.take_back_to(self.moves.len() - 1);
     }
 
-    fn write_tcp_message(&mut self, msg: &str) {
+    fn redo(&mut self) {
+
+        let Some(played) = self.redo_moves.pop() else {
+            return;
+        };
 
-        match self.stream.write(msg.as_bytes()) {
+        // play_move() forgets the undone moves, but we want to keep the rest of them
+        let redo_moves = std::mem::take(&mut self.redo_moves);
 
-            Ok(_) => {println!("Move sent to opponent!")},
-            Err(e) => {println!("Failed to write message: {}", e)}
+        if let Err(e) = self.play_move(played.mv) {
+            println!("Could not redo {}: {}", played.san, e);
         }
-
+        self.redo_moves = redo_moves;
+        self.clear_selection();
     }
 
+    fn send(&mut self, msg: &ProtocolMessage) {
 
+        if let Some(network_player) = &mut self.network_player {
+            network_player.write_tcp_message(msg);
+        }
+    }
 
-}
-
+    fn request_takeback(&mut self) {
 
+        let Some(network_player) = &self.network_player else {
+            return;
+        };
 
-struct HelperNetworkPlayer {
-    network_player: NetworkPlayer, 
-    game_state: GameState,
-}
+        if self.gameover || self.takeback_requested.is_some() {
+            return;
+        }
 
-impl HelperNetworkPlayer {
+        // take back our last move, and the opponent's reply if they already made one
+        let half_moves = if network_player.color == self.game.active_player() { 2 } else { 1 };
 
-    fn decode_move(chess_move: &str) -> Option<ChessMove> {
+        if self.moves.len() < half_moves {
+            println!("No move of yours to take back");
+            return;
+        }
 
-        let from = BoardPosition::try_from(&chess_move[0..2]).ok()?;
-        let to = BoardPosition::try_from(&chess_move[2..4]).ok()?;
+        let ply = self.moves.len() - half_moves;
+        self.takeback_requested = Some(ply);
+        self.send(&ProtocolMessage::TakebackRequest { ply });
+    }
 
-        let promotion = match chess_move.chars().nth(4) {
+    fn answer_offer(&mut self, accept: bool) {
 
-            Some('K') => Some(PromotionType::Knight),
-            Some('B') => Some(PromotionType::Bishop),
-            Some('R') => Some(PromotionType::Rook),
-            Some('Q') => Some(PromotionType::Queen),
-            Some('0') | None | _ => None,
+        let Some(offer) = self.offer.take() else {
+            return;
         };
 
-        return Some(ChessMove{piece_movement: PieceMovement{from, to}, promotion});
+        match offer {
+            _ if self.gameover => {} // the game ended some other way in the meantime
+            Offer::Takeback { ply } if accept => {
+                self.send(&ProtocolMessage::TakebackAccept { ply });
+                self.take_back_to(ply);
+            }
+            Offer::Takeback { .. } => self.send(&ProtocolMessage::TakebackDecline),
+            Offer::Draw { .. } if accept => {
+                self.send(&ProtocolMessage::DrawAccept);
+                self.end_by_agreement();
+            }
+            Offer::Draw { .. } => self.send(&ProtocolMessage::DrawDecline),
+        }
+    }
+
+    // the side the buttons and shortcuts act for: our color in network games,
+    // the player to move at a shared board
+    fn our_color(&self) -> PlayerColor {
 
+        match &self.network_player {
+            Some(network_player) => network_player.color,
+            None => self.game.active_player(),
+        }
     }
 
-    fn encode_move(mv: ChessMove) -> String {
+    // a game can be aborted until both sides have made their first move
+    fn can_abort(&self) -> bool {
+        !self.gameover && self.moves.len() < 2
+    }
 
-        let files = ["A", "B", "C", "D", "E", "F", "G", "H"];
-        let ranks = ["1", "2", "3", "4", "5", "6", "7", "8"];
+    fn end_by_resignation(&mut self, loser: PlayerColor) {
 
-        let move_string: String = format!(
-            "{}{}{}{}{}",
-            files[mv.piece_movement.from.file.get() as usize],
-            ranks[mv.piece_movement.from.rank.get() as usize],
-            files[mv.piece_movement.to.file.get() as usize],
-            ranks[mv.piece_movement.to.rank.get() as usize],
-            match mv.promotion {
-                Some(PromotionType::Knight) => "K",
-                Some(PromotionType::Bishop) => "B",
-                Some(PromotionType::Rook) => "R",
-                Some(PromotionType::Queen) => "Q",
-                None => "0",
-            }
-        );
+        let winner = opponent_color(loser);
 
-        return move_string;
+        self.forced_outcome = Some(Outcome::win(winner));
+        self.gameover_message = Some(format!("{:?} resigned,\n{:?} wins", loser, winner));
+        self.termination = Some(Termination::Normal("Resignation".to_string()));
+        self.end_game();
     }
 
-    fn board_to_fen(game: &ChessGame) -> String {
+    fn end_by_agreement(&mut self) {
 
-        // FEN-notation, excluding castling, en passant etc. (tracked by chess lib)
-        // code inspo from leben-chess impl Display for Board
+        self.forced_outcome = Some(Outcome::Draw);
+        self.gameover_message = Some("Draw by agreement".to_string());
+        self.termination = Some(Termination::Normal("Agreement".to_string()));
+        self.end_game();
+    }
 
-        let mut fen_board = String::new();
+    fn end_by_abort(&mut self, by: PlayerColor) {
 
-        let mut empty_squares = 0;
-        for rank in (0..8).rev() { // print eight rank first
+        self.forced_outcome = Some(Outcome::Ongoing);
+        self.gameover_message = Some(format!("{:?} aborted the game", by));
+        self.termination = Some(Termination::Abandoned);
+        self.end_game();
+        self.pgn_saved = true; // nothing worth keeping
+    }
 
-            if rank < 7 {
-                if empty_squares != 0 {
-                    fen_board += &empty_squares.to_string();
-                }
-                fen_board += "/";
-            }
-            empty_squares = 0;
-            for file in 0..8 { // print a8, b8, ..., h8 etc.
+    fn resign(&mut self) {
 
-                let pos = BoardPosition{
-                    file: file.try_into().unwrap(),
-                    rank: rank.try_into().unwrap(),
-                };
+        if self.gameover {
+            return;
+        }
 
-                let piece = game.board().get_piece(pos);
+        let color = self.our_color();
+        self.send(&ProtocolMessage::Resign);
+        self.end_by_resignation(color);
+    }
 
-                if let Some(piece) = piece {
-                    if empty_squares != 0 {
-                        fen_board += &empty_squares.to_string();
-                        empty_squares = 0;
-                    }
-                    fen_board += piece.get_char();
+    fn offer_draw(&mut self) {
 
-                } else {
-                    empty_squares += 1;
-                }
+        if self.gameover {
+            return;
+        }
+
+        if self.network_player.is_some() {
 
+            if self.draw_offered {
+                println!("Already offered a draw, waiting for the answer");
+                return;
             }
+            self.draw_offered = true;
+            self.send(&ProtocolMessage::DrawOffer);
+        } else {
+            // the other player at the board answers
+            self.offer = Some(Offer::Draw { from: self.game.active_player() });
         }
+    }
 
-        println!("{}", fen_board);
+    fn abort(&mut self) {
 
-        return fen_board;
-        
+        if !self.can_abort() {
+            println!("The game can only be aborted before both sides have moved");
+            return;
+        }
+
+        let color = self.our_color();
+        self.send(&ProtocolMessage::Abort);
+        self.end_by_abort(color);
     }
 
+    fn press(&mut self, button: PanelButton) {
+
+        match button {
+            PanelButton::Resign => self.resign(),
+            PanelButton::OfferDraw => self.offer_draw(),
+            PanelButton::Abort => self.abort(),
+        }
+    }
 
-     fn decode_message(msg: &str) -> Result<(&str, &str, &str), &'static str>{
+    // number of moves played on the board that is shown
+    fn shown_ply(&self) -> usize {
+        self.history.as_ref().map_or(self.moves.len(), |(ply, _)| *ply)
+    }
 
-        // if (msg).len() < 128 {
-        //     return Err("Message too short");
-        // }
+    // show the position after the first `ply` moves, the live game isn't touched
+    fn show_ply(&mut self, ply: usize) {
 
-        let parts: Vec<&str> = msg.trim().split(":").collect();
+        if ply.min(self.moves.len()) == self.shown_ply() {
+            return;
+        }
 
-        println!("{:?}", parts);
+        self.clear_selection();
 
-        if parts.len() < 4 {
-            return Err("Invalid message format");
+        if ply >= self.moves.len() {
+            self.history = None;
+            return;
         }
 
-        if parts[0].to_string() != "ChessMOVE".to_string() {
-            return Err("invalid message ID");
+        // same as rebuild_game(), on a copy
+        let (mut game, _) = GameState::starting_position(&self.start_fen).unwrap(); // already validated in new()
+
+        for played in &self.moves[..ply] {
+            let _ = game.do_move(played.mv);
         }
 
-        Ok((parts[1], parts[2], parts[3]))
+        self.history = Some((ply, game));
+    }
+
+    fn move_list(&self) -> MoveList<'_> {
+        MoveList::new(&self.moves, &self.start_position, self.shown_ply())
     }
 
+    fn pgn(&self) -> PgnGame<'_> {
+
+        // only games that didn't start from the initial position need the FEN tag
+        let start_fen = self.start_fen.as_deref().filter(|start_fen| *start_fen != fen::default_fen());
+        let start_position = self.start_position.clone();
+
+        let status = self.game.game_status();
+        let termination = self.termination.clone().or_else(|| pgn::termination(&status));
+        let outcome = self.forced_outcome.unwrap_or(Outcome::from(status));
+
+        PgnGame {
+            event: "Casual game".to_string(),
+            site: "?".to_string(),
+            white: "?".to_string(),
+            black: "?".to_string(),
+            termination,
+            outcome,
+            start_fen,
+            start_position,
+            moves: &self.moves,
+        }
+    }
 
+    fn save_pgn(&mut self) {
 
-    fn encode_message(game: &ChessGame, mv: ChessMove) -> String{  
+        match self.pgn().save() {
+            Ok(path) => println!("Saved game to {}", path.display()),
+            Err(e) => println!("Could not save game: {}", e),
+        }
+        self.pgn_saved = true;
+    }
 
-        //let separator = ":";
-        //let msg_id = "ChessMOVE";
+    fn start_from_fen_dialog(&mut self) -> GameResult {
 
-        let game_status = match game.game_status() {
-            
-            GameStatus::NotYetStarted | GameStatus::Normal => "0-0",
-            GameStatus::Win(PlayerColor::White, _) => "1-0",
-            GameStatus::Win(PlayerColor::Black, _) => "0-1",
-            GameStatus::Draw(_) => "1-1",
+        let Some(dialog) = &mut self.fen_dialog else {
+            return Ok(());
         };
 
-        let encoded_move = HelperNetworkPlayer::encode_move(mv);
+        let text = dialog.text.trim().to_string();
 
-        let fen = HelperNetworkPlayer::board_to_fen(game); // Make FEN string
+        // check it before throwing away the current game
+        if let Err(e) = fen::new_game(&text) {
+            dialog.error = Some(format!("Invalid FEN: {}", e));
+            return Ok(());
+        }
 
-        return format!(
-            "ChessMOVE:{}:{}:{}:{}",
-            encoded_move,
-            game_status,
-            fen,
-            "0".repeat(MSG_SIZE-9-5-3-fen.len()-4)
-        );
+        self.start_fen = Some(text);
+        self.fen_dialog = None;
+        self.reset()
     }
 
-}
+    fn open_replay(&mut self, path: &Path) {
 
+        // the network game would go on without us looking at it
+        if self.network_player.is_some() || self.lobby.is_some() {
+            println!("Can't open a replay during a network game");
+            return;
+        }
 
+        match Replay::load(path) {
+            Ok(replay) => {
+                if let Some(e) = &replay.error {
+                    println!("{}: {}", path.display(), e);
+                }
+                self.replay = Some(replay);
+            }
+            Err(e) => println!("Could not load {}: {}", path.display(), e),
+        }
+    }
 
+    // called by our event loop, ggez has no callback for this
+    fn file_dropped_event(&mut self, _ctx: &mut Context, path: &Path) -> GameResult {
 
-// implement eventhandler, which requires update and draw functions
-impl event::EventHandler for GameState {
+        self.fen_dialog = None;
+        self.open_replay(path);
 
-    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+        Ok(())
+    }
 
-        match self.game.game_status(){
+    fn draw_side_panel(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
 
-            GameStatus::Win(_,_) => {self.gameover = true;}
-            GameStatus::Draw(_) => {self.gameover = true;}
+        // no room for it in narrow windows, the keyboard shortcuts still work
+        let Some(panel) = self.layout.panel else {
+            return Ok(());
+        };
 
-            _ => {}
+        let background = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(0.0, 0.0, panel.w, panel.h),
+            Color::from_rgb(30, 30, 30),
+        )?;
+        canvas.draw(&background, Vec2::new(panel.x, panel.y));
+
+        if let Some(clock) = &self.clock {
 
+            // Black's clock on Black's side of the board
+            clock.draw(ctx, canvas, panel_clock_area(panel, true), opponent_color(self.board.orientation))?;
+            clock.draw(ctx, canvas, panel_clock_area(panel, false), self.board.orientation)?;
         }
 
-        if self.gameover {
-            // Game over, give user option to restart the game
-            self.show_gameover_popup = true;
+        if self.lobby.is_some() || self.replay.is_some() {
             return Ok(());
         }
 
+        // next to each clock, what that side took in the shown position
+        let shown_board = self.history.as_ref().map_or(self.game.board(), |(_, game)| game.board());
+        let shown_moves = &self.moves[..self.shown_ply()];
+        let balance = material::balance(shown_board);
 
-        // If we're waiting for the opponent to make a move (networking)
+        for (color, top) in [(opponent_color(self.board.orientation), true), (self.board.orientation, false)] {
+            let taken = material::captured_by(shown_moves, color);
+            material::draw_captures(canvas, &self.atlas, panel_captures_area(panel, top), &taken, color, balance);
+        }
 
-        if let Some(network_player) = &mut self.network_player {
+        self.move_list().draw(ctx, canvas, move_list_area(panel))?;
 
-            // only read their message if it's their turn
-            if network_player.color == self.game.active_player() {
-                return Ok(());
-            }
+        for button in PanelButton::ALL {
 
-            if let Some(msg) = network_player.read_tcp_message()? {
+            let available = match button {
+                PanelButton::Resign => !self.gameover,
+                PanelButton::OfferDraw => !self.gameover && !self.draw_offered,
+                PanelButton::Abort => self.can_abort(),
+            };
+            let color = if available { Color::from_rgb(70, 70, 90) } else { Color::from_rgb(50, 50, 50) };
 
+            button::draw_button(ctx, canvas, button.area(panel), button.label(), color)?;
+        }
 
-                match HelperNetworkPlayer::decode_message(&msg) {
+        Ok(())
+    }
 
-                    Ok((chess_move, game_state, new_board)) => {
+    fn fen(&self) -> String {
+        fen::write(self.game.board(), &self.position)
+    }
 
-                        let decoded_move = HelperNetworkPlayer::decode_move(chess_move).unwrap();
 
-                        // perform move
-                        match self.game.do_move(decoded_move) {
 
-                            Ok(_) => {},
-                            Err(e) => {
-                                // RAGE QUIT
-                                self.gameover = true;
-                                self.network_player = None; // drop connection
-                                println!("Rage Quit! Failed to perform opponent's move: {}", e);
-                                return Ok(());
-                            }
-                        }
+}
 
-                        // compare your new board with opponent's new board
+ 
 
-                        let new_board_fen = HelperNetworkPlayer::board_to_fen(&self.game);
 
-                        if new_board_fen != new_board {
-                            // RAGE QUIT
-                            self.gameover = true;
-                            self.network_player = None;
-                            println!("Rage Quit! FEN-board mismatch");
-                            return  Ok(());
-                        }
+struct Highlight {
 
-                        if game_state != "0-0" {
-                            self.gameover = true;
-                            return Ok(());
-                        } 
+    mesh: Option<graphics::Mesh>,
 
-        
-                    }
+}
 
-                    Err(e) => {
-                        println!("Error decoding message: {}", e);
-                        return Ok(())
-                    }
-                }
+impl Highlight {
 
-            }
-        }
-       
+    fn new(ctx: &mut Context) -> GameResult<Self> {
 
+        // white and 1x1, tinted with the theme colors and scaled to the square size when drawn
+        let mesh = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(0.0, 0.0, 1.0, 1.0),
+            Color::WHITE,
+        )?;
 
-        Ok(())
+        Ok(Highlight {
+            mesh: Some(mesh),
+        })
     }
 
-    fn draw(&mut self, ctx: &mut Context) -> Result<(), ggez::GameError> {
 
-         // canvas that renders to the frame
-        let mut canvas = graphics::Canvas::from_frame(
-            ctx,
-            graphics::Color::from([1.0, 0.0, 0.0, 0.0]),
-        );
+    fn draw (&self, canvas: &mut Canvas, board: &ChessBoard, theme: &Theme, last_move: Option<ChessMove>, selected: Option<BoardPosition>) -> GameResult{
 
-        self.board.draw(ctx, &mut canvas, &self.game.board())?;
+        let Some(mesh) = &self.mesh else {
+            return Ok(());
+        };
 
-        self.highlight.draw(&mut canvas)?;
+        if let Some(mv) = last_move {
 
+            for boardpos in [mv.piece_movement.from, mv.piece_movement.to] {
 
-        if self.show_gameover_popup {
+                let squares_pos = board.calc_square_pos(boardpos);
+                canvas.draw(mesh, DrawParam::default().dest(squares_pos).scale(Vec2::splat(board.square_size())).color(theme.last_move));
+            }
+        }
+       
+        if let Some(boardpos) = selected {
 
-            let overlay = graphics::Mesh::new_rectangle(
-                ctx,
-                graphics::DrawMode::fill(),
-                graphics::Rect::new(0.0,0.0, WIDTH/4.0, HEIGHT/4.0),
-                Color::from_rgba(120, 0, 0, 160),
-            )?;
-            canvas.draw(&overlay, Vec2::new(SQUARE_SIZE*3.0, SQUARE_SIZE*3.0));
+            let squares_pos = board.calc_square_pos(boardpos);
+            canvas.draw(mesh, DrawParam::default().dest(squares_pos).scale(Vec2::splat(board.square_size())).color(theme.selected));
+        }
 
-            let msg = format!("Game over!\n{}\nClick to restart.", self.game.game_status());
-            let mut text = graphics::Text::new(msg);
+        Ok(())
+    }
 
+    // red glow under the king of the side to move, if it's in check
+    fn draw_check(&self, ctx: &mut Context, canvas: &mut Canvas, board: &ChessBoard, theme: &Theme, game: &ChessGame) -> GameResult {
 
-            text.set_scale(32.0);
-            let text_x = SQUARE_SIZE*3.0 + SQUARE_SIZE/2.0 - (text.measure(ctx).unwrap().y)/2.0;
-            let text_y = SQUARE_SIZE*3.0 + SQUARE_SIZE/2.0;
-            canvas.draw(&text, DrawParam::default().dest([text_x, text_y]));
+        let color = game.active_player();
 
+        if !san::in_check(game.board(), color) {
+            return Ok(());
         }
+        let Some(king) = san::king_square(game.board(), color) else {
+            return Ok(());
+        };
 
-        if self.promotion {
+        let square_size = board.square_size();
+        let center = board.calc_square_pos(king) + Vec2::splat(square_size/2.0);
 
-            let overlay = graphics::Mesh::new_rectangle(
-                ctx,
-                graphics::DrawMode::fill(),
-                graphics::Rect::new(0.0,0.0, WIDTH/4.0, HEIGHT/4.0),
-                Color::from_rgba(0, 100, 0, 160),
-            )?;
-            canvas.draw(&overlay, Vec2::new(SQUARE_SIZE*3.0, SQUARE_SIZE*3.0));
+        // stacked translucent circles, getting stronger towards the center
+        let mut builder = graphics::MeshBuilder::new();
+        for i in 0..5 {
+            let radius = square_size*(0.5 - 0.08*i as f32);
+            let glow = Color { a: theme.check.a / 4.0, ..theme.check };
+            builder.circle(graphics::DrawMode::fill(), center, radius, 0.5, glow)?;
+        }
 
-            
-            match self.game.active_player() {
+        let mesh = graphics::Mesh::from_data(ctx, builder.build());
+        canvas.draw(&mesh, DrawParam::default());
 
-                PlayerColor::White => {
+        Ok(())
+    }
 
-                    let knight = ChessPiece{
-                        piece: Piece{piece_type: PieceType::Knight, player: PlayerColor::White},
-                        position: BoardPosition {file: U3::try_from(3).unwrap(), rank: U3::try_from(4).unwrap()}
-                    };
-                    let bishop = ChessPiece { 
-                        piece: Piece{piece_type: PieceType::Bishop, player: PlayerColor::White},
-                        position: BoardPosition {file: U3::try_from(4).unwrap(), rank: U3::try_from(4).unwrap()}
-                    };
-                    let rook = ChessPiece { 
-                        piece: Piece{piece_type: PieceType::Rook, player: PlayerColor::White},
-                        position: BoardPosition {file: U3::try_from(3).unwrap(), rank: U3::try_from(3).unwrap()}
-                    };
-                    let queen = ChessPiece { 
-                        piece: Piece{piece_type: PieceType::Queen, player: PlayerColor::White},
-                        position: BoardPosition {file: U3::try_from(4).unwrap(), rank: U3::try_from(3).unwrap()}
-                    };
+    // dots on the squares the selected piece can move to, rings around the pieces it can capture.
+    // all of them go into one mesh, so it's a single draw call
+    fn draw_legal_moves(&self, ctx: &mut Context, canvas: &mut Canvas, chess_board: &ChessBoard, theme: &Theme, game: &ChessGame, from: BoardPosition) -> GameResult {
+
+        let targets = game.available_moves(from);
+        let board = game.board();
+        let color = theme.legal_move;
+        let square_size = chess_board.square_size();
 
-                    let promotion_pieces = [knight, bishop, rook, queen];
+        let mut builder = graphics::MeshBuilder::new();
+        let mut empty = true;
 
-                    for piece in promotion_pieces {
+        for i in 0..64 {
 
-                        let image_path = Some(piece.filename()).unwrap();
-                        let piece_image = Image::from_path(ctx, image_path)?;
+            let to = fen::square(i % 8, i / 8);
+            if !targets.get(to) {
+                continue;
+            }
 
-                        let scale = Vec2::new(SQUARE_SIZE / piece_image.width() as f32, SQUARE_SIZE / piece_image.height() as f32);
+            // en passant captures land on an empty square
+            let en_passant = board.get_piece(from).is_some_and(|piece| piece.piece_type == PieceType::Pawn) && from.file.get() != to.file.get();
+            let capture = board.get_piece(to).is_some() || en_passant;
 
-                        let (col, row): (u8, u8) = piece.position.into();
-                        let x = col as f32 * SQUARE_SIZE;
-                        let y = row as f32 * SQUARE_SIZE;
-        
-                        canvas.draw(&piece_image, DrawParam::default()
-                                    .dest(Vec2::new(x as f32, y as f32))
-                                    .scale(scale));
+            let center = chess_board.calc_square_pos(to) + Vec2::splat(square_size/2.0);
+
+            if capture {
+                builder.circle(graphics::DrawMode::stroke(square_size*0.08), center, square_size*0.44, 0.5, color)?;
+            } else {
+                builder.circle(graphics::DrawMode::fill(), center, square_size*0.15, 0.5, color)?;
+            }
+            empty = false;
+        }
+
+        // a mesh without vertices can't be uploaded to the GPU
+        if empty {
+            return Ok(());
+        }
+
+        let mesh = graphics::Mesh::from_data(ctx, builder.build());
+        canvas.draw(&mesh, DrawParam::default());
+
+        Ok(())
+    }
+
+}
+// implement eventhandler, which requires update and draw functions
+impl event::EventHandler for GameState {
+
+    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+
+        if let Some(animation) = &mut self.animation
+            && animation.advance(_ctx.time.delta()) {
+            self.animation = None;
+        }
+
+        // still setting up the connection, nothing else to do until the opponent is here
+        if let Some(lobby) = &mut self.lobby {
+
+            if let Some(network_player) = lobby.poll() {
+
+                // the host decides where the game starts, and the client already checked the FEN
+                self.start_fen = Some(network_player.start_fen.clone());
+                (self.game, self.position) = GameState::starting_position(&self.start_fen)
+                    .map_err(|e| ggez::GameError::CustomError(format!("Invalid FEN: {}", e)))?;
+                self.start_position = self.position.clone();
+                self.moves.clear();
+
+                self.time_control = network_player.time_control;
+                self.clock = self.time_control.map(Clock::new);
+
+                // our own pieces at the bottom
+                self.board.orientation = network_player.color;
+
+                self.network_player = Some(network_player);
+                self.lobby = None;
+            }
+            return Ok(());
+        }
+
+        if self.replay.is_some() {
+            return Ok(());
+        }
+
+        match self.game.game_status(){
+
+            GameStatus::Win(_,_) => {self.end_game();}
+            GameStatus::Draw(_) => {self.end_game();}
+
+            _ => {}
+
+        }
+
+        // in network games only the player whose clock it is declares the flag fall,
+        // the other side waits for the Timeout message (their move might still be on the way)
+        if let Some(flagged) = self.clock.as_ref().and_then(Clock::flagged) {
+
+            let our_flag = match &self.network_player {
+                Some(network_player) => network_player.color == flagged,
+                None => true,
+            };
+
+            if !self.gameover && our_flag {
+                self.send(&ProtocolMessage::Timeout { color: flagged });
+                self.end_on_time(flagged);
+            }
+        }
+
+        // the rest of frames the socket couldn't take at once, also after the game ended (e.g. Resign)
+        if let Some(network_player) = &mut self.network_player {
+            network_player.flush();
+        }
+
+        if self.gameover {
+            // Game over, give user option to restart the game
+            if let Some(clock) = &mut self.clock {
+                clock.stop();
+            }
+
+            // nothing left to agree on, a takeback now would only put the two boards out of sync
+            self.offer = None;
+            self.takeback_requested = None;
+            self.draw_offered = false;
+
+            if !self.pgn_saved && !self.moves.is_empty() {
+                self.save_pgn();
+            }
+            self.show_gameover_popup = true;
+            return Ok(());
+        }
+
+
+        // networking: the opponent's moves, and takeback requests/answers at any time
+
+        let event = match &mut self.network_player {
+            Some(network_player) => network_player.read_tcp_message(),
+            None => None,
+        };
+
+        match event {
+
+            Some(NetworkEvent::Disconnected(reason)) => {
+                self.end_game();
+                self.gameover_message = Some(reason);
+                self.termination = Some(Termination::Abandoned);
+                self.network_player = None;
+                return Ok(());
+            }
+
+            Some(NetworkEvent::Message(ProtocolMessage::Move { mv, status, fen, clock })) => {
+
+                if self.network_player.as_ref().is_some_and(|network_player| network_player.color == self.game.active_player()) {
+                    println!("Ignoring move sent while it's our turn");
+                    return Ok(());
+                }
+
+                // perform move
+                match self.play_move(mv) {
+
+                    Ok(_) => {},
+                    Err(e) => {
+                        // RAGE QUIT
+                        self.end_game();
+                        self.termination = Some(Termination::RulesInfraction);
+                        self.network_player = None; // drop connection
+                        println!("Rage Quit! Failed to perform opponent's move: {}", e);
+                        return Ok(());
                     }
                 }
-                
-                PlayerColor::Black => {
 
-                    let knight = ChessPiece{
-                        piece: Piece{piece_type: PieceType::Knight, player: PlayerColor::Black},
-                        position: BoardPosition {file: U3::try_from(3).unwrap(), rank: U3::try_from(5).unwrap()}
-                    };
-                    let bishop = ChessPiece { 
-                        piece: Piece{piece_type: PieceType::Bishop, player: PlayerColor::Black},
-                        position: BoardPosition {file: U3::try_from(4).unwrap(), rank: U3::try_from(5).unwrap()}
-                    };
-                    let rook = ChessPiece { 
-                        piece: Piece{piece_type: PieceType::Rook, player: PlayerColor::Black},
-                        position: BoardPosition {file: U3::try_from(3).unwrap(), rank: U3::try_from(4).unwrap()}
-                    };
-                    let queen = ChessPiece { 
-                        piece: Piece{piece_type: PieceType::Queen, player: PlayerColor::Black},
-                        position: BoardPosition {file: U3::try_from(4).unwrap(), rank: U3::try_from(4).unwrap()}
-                    };
+                // the opponent measured their own time, that's the one that counts
+                if let (Some(game_clock), Some((white, black))) = (&mut self.clock, clock) {
 
-                
+                    let mover = opponent_color(self.game.active_player());
+                    let remaining = if mover == PlayerColor::White { white } else { black };
+                    game_clock.set_remaining(mover, remaining);
+                }
 
-                    let promotion_pieces = [knight, bishop, rook, queen];
+                // compare your new board with opponent's new board
 
-                    for piece in promotion_pieces {
+                if !fen::same_position(&self.fen(), &fen) {
+                    // RAGE QUIT
+                    self.end_game();
+                    self.termination = Some(Termination::RulesInfraction);
+                    self.network_player = None;
+                    println!("Rage Quit! FEN-board mismatch");
+                    return  Ok(());
+                }
+
+                if status != Outcome::Ongoing {
+                    self.end_game();
+                    return Ok(());
+                }
+            }
 
-                        let image_path = Some(piece.filename()).unwrap();
-                        let piece_image = Image::from_path(ctx, image_path)?;
+            Some(NetworkEvent::Message(ProtocolMessage::Timeout { color })) => {
 
-                        let scale = Vec2::new(SQUARE_SIZE / piece_image.width() as f32, SQUARE_SIZE / piece_image.height() as f32);
+                if self.network_player.as_ref().is_some_and(|network_player| network_player.color != color) && self.clock.is_some() {
+                    self.end_on_time(color);
+                } else {
+                    println!("Ignoring timeout for {:?}", color);
+                }
+            }
 
-                        let (col, row): (u8, u8) = piece.position.into();
-                        let x = col as f32 * SQUARE_SIZE;
-                        let y = row as f32 * SQUARE_SIZE;
+            Some(NetworkEvent::Message(ProtocolMessage::TakebackRequest { ply })) => {
 
-                        canvas.draw(&piece_image, DrawParam::default()
-                                    .dest(Vec2::new(x as f32, y as f32))
-                                    .scale(scale));
-                    }
+                if ply < self.moves.len() {
+                    self.offer = Some(Offer::Takeback { ply });
+                } else {
+                    println!("Declining takeback to move {}, we're only at {}", ply, self.moves.len());
+                    self.send(&ProtocolMessage::TakebackDecline);
+                }
+            }
+
+            Some(NetworkEvent::Message(ProtocolMessage::TakebackAccept { ply })) => {
+
+                if self.takeback_requested.take() == Some(ply) {
+                    self.take_back_to(ply);
+                } else {
+                    println!("Ignoring takeback we didn't ask for");
+                }
+            }
+
+            Some(NetworkEvent::Message(ProtocolMessage::TakebackDecline)) => {
+                println!("Opponent declined the takeback");
+                self.takeback_requested = None;
+            }
 
+            Some(NetworkEvent::Message(ProtocolMessage::Resign)) => {
+                let color = self.our_color();
+                self.end_by_resignation(opponent_color(color));
+            }
+
+            Some(NetworkEvent::Message(ProtocolMessage::DrawOffer)) => {
+                let color = self.our_color();
+                self.offer = Some(Offer::Draw { from: opponent_color(color) });
+            }
+
+            Some(NetworkEvent::Message(ProtocolMessage::DrawAccept)) => {
+
+                if self.draw_offered {
+                    self.draw_offered = false;
+                    self.end_by_agreement();
+                } else {
+                    println!("Ignoring draw we didn't offer");
+                }
+            }
+
+            Some(NetworkEvent::Message(ProtocolMessage::DrawDecline)) => {
+                println!("Opponent declined the draw");
+                self.draw_offered = false;
+            }
+
+            Some(NetworkEvent::Message(ProtocolMessage::Abort)) => {
+
+                // our first move may have crossed their abort on the wire
+                if self.moves.len() <= 2 {
+                    let color = self.our_color();
+                    self.end_by_abort(opponent_color(color));
+                } else {
+                    println!("Ignoring abort after move {}", self.moves.len());
                 }
+            }
 
+            Some(NetworkEvent::Message(ProtocolMessage::Init { .. })) => {
+                println!("Ignoring unexpected handshake from opponent");
+            }
 
+            Some(NetworkEvent::Invalid(e)) => {
+                println!("Error decoding message: {}", e);
             }
 
+            None => {}
         }
 
 
-        canvas.finish(ctx)?;
 
         Ok(())
-        
     }
 
+    fn draw(&mut self, ctx: &mut Context) -> Result<(), ggez::GameError> {
 
-    fn mouse_button_down_event( // https://docs.rs/ggez/latest/ggez/event/trait.EventHandler.html#method.mouse_button_down_event
-            &mut self,
-            _ctx: &mut Context,
-            _button: event::MouseButton,
-            _x: f32, // corresponds to column
-            _y: f32, // corresponds to row
-        ) -> Result<(), ggez::GameError> {
+        let started = Instant::now();
 
-        match _button {
-            MouseButton::Left => {
+         // canvas that renders to the frame
+        let mut canvas = graphics::Canvas::from_frame(
+            ctx,
+            graphics::Color::from([1.0, 0.0, 0.0, 0.0]),
+        );
+        // the board and the side panel are in window pixels, dialogs in WIDTH x HEIGHT on top of the board
+        canvas.set_screen_coordinates(self.layout.window);
 
-                if let Some(network_player) = &self.network_player {
+        self.draw_side_panel(ctx, &mut canvas)?;
 
-                    if network_player.color != self.game.active_player() {
-                        println!("Opponent is to move");
-                        return Ok(());
-                    }
-                }
+        if self.settings.show_frame_time {
+            self.frame_timer.draw(&mut canvas, Vec2::new(self.layout.window.x + 4.0, self.layout.window.y + 4.0));
+        }
 
+        if let Some(replay) = &self.replay {
 
-                if !self.show_gameover_popup {
+            self.board.draw(ctx, &mut canvas, &self.board_themes[self.board_theme], self.settings.show_coordinates, &mut self.atlas, replay.game.board())?;
 
-                    // convert (x,y)-coordinates to GuiPosition
-                    let row = (_y / SQUARE_SIZE).floor() as u8;
-                    let col = (_x / SQUARE_SIZE).floor() as u8;
-                    
-                    let gui_position = BoardPosition {file: U3::try_from(col).unwrap(), rank: U3::try_from(row).unwrap()};
-                    let board_position = inverse_boardpos_guipos(gui_position);
+            canvas.set_screen_coordinates(self.layout.overlay_coordinates());
+            replay.draw(ctx, &mut canvas)?;
 
-                    let rank = board_position.rank.get(); 
-                    let file = board_position.file.get();
+            canvas.finish(ctx)?;
+            self.frame_timer.record(started.elapsed());
+            return Ok(());
+        }
 
+        // highlights go between the squares and the pieces
+        self.board.draw_squares(ctx, &mut canvas, &self.board_themes[self.board_theme])?;
 
-                    if self.selected_square == None {
+        if self.settings.show_coordinates {
+            self.board.draw_coordinates(&mut canvas, &self.board_themes[self.board_theme]);
+        }
 
-                        // if the square contains a piece with valid moves, select it
-                        // game.available_moves(BoardPosition::try_from((row,col)).unwrap()); returns a bitmap containing all zeroes if there's no available moves.
+        // an earlier position from the move list, or the game
+        let (shown_game, last_move) = match &self.history {
+            Some((ply, game)) => (game, ply.checked_sub(1).map(|index| self.moves[index].mv)),
+            None => (&self.game, self.moves.last().map(|played| played.mv)),
+        };
 
-                        let bitboard = self.game.available_moves(BoardPosition::try_from((file, rank)).unwrap()); // (file, rank)
-                        
-                        if !bitboard.is_all_zeros() {
+        let highlights = &self.board_themes[self.board_theme].highlights;
+        self.highlight.draw(&mut canvas, &self.board, highlights, last_move, self.input.selected())?;
+        self.highlight.draw_check(ctx, &mut canvas, &self.board, highlights, shown_game)?;
 
-                            self.selected_square = Some(board_position);
-                            self.highlight.selected_square = Some(board_position);
-                        }
+        // the last move's animation belongs to the live game
+        let animation = self.animation.as_ref().filter(|_| self.history.is_none());
 
-                    } else if self.selected_target == None { // normal move
+        let mut hidden: Vec<BoardPosition> = self.input.dragged().map(|(from, _)| from).into_iter().collect();
+        if let Some(animation) = animation {
+            hidden.extend(animation.hidden());
+        }
+        self.board.draw_pieces(&mut canvas, &mut self.atlas, shown_game.board(), &hidden);
 
-                        // now the player clicks the target square, check if the target square is valid
+        if let Some(animation) = animation {
+            animation.draw(&mut canvas, &self.atlas, &self.board);
+        }
 
-                        let selected = self.selected_square.unwrap();
-                        let selected_rank = selected.rank.get();
-                        let seleceted_file = selected.file.get();
+        if self.settings.show_legal_moves && self.input.promotion().is_none()
+            && let Some(from) = self.input.selected() {
+            self.highlight.draw_legal_moves(ctx, &mut canvas, &self.board, highlights, &self.game, from)?;
+        }
 
-                        let targeted_rank = rank;
-                        let targeted_file = file;
+        // the dragged piece goes on top of everything on the board, centered on the cursor
+        if let Some((from, cursor)) = self.input.dragged()
+            && let Some(piece) = self.game.board().get_piece(from) {
 
-                        self.selected_target = Some(board_position);
+            let square_size = self.board.square_size();
+            self.atlas.draw_piece(&mut canvas, piece, cursor - Vec2::splat(square_size/2.0), square_size);
+        }
 
+        if let Some(picker) = self.promotion_picker() {
+            picker.draw(ctx, &mut canvas, &self.atlas)?;
+        }
 
-                        let from = BoardPosition::try_from((seleceted_file, selected_rank)).unwrap();
-                        let to = BoardPosition::try_from((targeted_file, targeted_rank)).unwrap();
+        canvas.set_screen_coordinates(self.layout.overlay_coordinates());
 
-                        // check if move is promotion
+        // out of the way while looking through the moves
+        if self.show_gameover_popup && self.history.is_none() {
 
-                        let promotion_expected = self.game.expects_promotion_move(from);
+            let overlay = graphics::Mesh::new_rectangle(
+                ctx,
+                graphics::DrawMode::fill(),
+                graphics::Rect::new(0.0,0.0, WIDTH/4.0, HEIGHT/4.0),
+                Color::from_rgba(120, 0, 0, 160),
+            )?;
+            canvas.draw(&overlay, Vec2::new(SQUARE_SIZE*3.0, SQUARE_SIZE*3.0));
 
-                        if promotion_expected {  // they have to pick a promotion piece type first.
-                            self.promotion = true;
-                            return Ok(());
-                        }
+            let reason = match &self.gameover_message {
+                Some(message) => message.clone(),
+                None => self.game.game_status().to_string(),
+            };
+            let msg = format!("Game over!\n{}\nClick to restart.", reason);
+            let mut text = graphics::Text::new(msg);
 
-                        let mv = ChessMove {
-                            piece_movement: PieceMovement {
-                                from: from,
-                                to: to,
-                            },
-                            promotion: None,
-                        };
-
-                        match self.game.do_move(mv) {
-                            Ok(_) => {
-                                println!("Move executed!");
-
-                                // only write move if it's legal
-
-                                if let Some(network_player) = &mut self.network_player {
-
-                                    let mv_tcp = HelperNetworkPlayer::encode_message(&self.game, mv);
-                                    NetworkPlayer::write_tcp_message(network_player, &mv_tcp);
-                                }
-                            }
-                            Err(err) => {
-                                println!("Illegal move: {:?}", err);
-                            }
-                        }
 
-                        self.selected_square = None;
-                        self.selected_target = None;
-                        self.highlight.selected_square = None;
-                        
-                        
+            text.set_scale(32.0);
+            let text_x = SQUARE_SIZE*3.0 + SQUARE_SIZE/2.0 - (text.measure(ctx).unwrap().y)/2.0;
+            let text_y = SQUARE_SIZE*3.0 + SQUARE_SIZE/2.0;
+            canvas.draw(&text, DrawParam::default().dest([text_x, text_y]));
 
-                    } else if self.promotion {
+        }
 
-                        let promotion_type = match (rank, row) {
+        if let Some(lobby) = &self.lobby {
+            lobby.draw(ctx, &mut canvas)?;
+        }
 
-                            (3, 3) => PromotionType::Knight,
-                            (3, 4) => PromotionType::Bishop,
-                            (4, 3) => PromotionType::Rook,
-                            (4, 4) => PromotionType::Queen,
+        if let Some(fen_dialog) = &self.fen_dialog {
+            fen_dialog.draw(ctx, &mut canvas)?;
+        }
 
-                            _ => {return Ok(())},
-                        };
+        if let Some(offer) = &self.offer {
+            offer.draw(ctx, &mut canvas)?;
+        }
 
-                        let selected_square = self.selected_square.unwrap();
-                        let selected_rank = selected_square.rank.get();
-                        let seleceted_file = selected_square.file.get();
+        canvas.finish(ctx)?;
+        self.frame_timer.record(started.elapsed());
 
-                        let selected_target = self.selected_target.unwrap();
-                        let targeted_rank = selected_target.rank.get();
-                        let targeted_file = selected_target.file.get();
+        Ok(())
+        
+    }
 
-                        let from = BoardPosition::try_from((seleceted_file, selected_rank)).unwrap();
-                        let to = BoardPosition::try_from((targeted_file, targeted_rank)).unwrap();
 
-                        let mv = ChessMove {
-                            piece_movement: PieceMovement {
-                                from: from,
-                                to: to,
-                            },
-                            promotion: Some(promotion_type),
-                        };
+    fn mouse_button_down_event( // https://docs.rs/ggez/latest/ggez/event/trait.EventHandler.html#method.mouse_button_down_event
+            &mut self,
+            _ctx: &mut Context,
+            _button: event::MouseButton,
+            _x: f32, // corresponds to column
+            _y: f32, // corresponds to row
+        ) -> Result<(), ggez::GameError> {
 
-                        match self.game.do_move(mv) {
-                            Ok(_) => {
-                                println!("Move executed!");
+        // the dialogs have their own coordinates
+        let overlay_point = self.layout.overlay_point(Vec2::new(_x, _y));
 
-                                // only write move if it's valid
+        match _button {
+            MouseButton::Left => {
 
-                                if let Some(network_player) = &mut self.network_player {
+                if self.lobby.is_some() {
 
-                                    let mv_tcp = HelperNetworkPlayer::encode_message(&self.game, mv);
-                                    NetworkPlayer::write_tcp_message(network_player, &mv_tcp);
-                                }
-                            }
-                            Err(err) => {
-                                println!("Illegal move: {:?}", err);
-                            }
-                        }
+                    // dropping the lobby closes the listener, then it's a local game
+                    if Lobby::cancel_button().contains(overlay_point) {
+                        self.lobby = None;
+                        self.reset()?;
+                    }
+                    return Ok(());
+                }
+
+                if self.fen_dialog.is_some() || self.replay.is_some() {
+                    return Ok(());
+                }
+
+                if self.offer.is_some() {
+
+                    if Offer::accept_button().contains(overlay_point) {
+                        self.answer_offer(true);
+                    } else if Offer::decline_button().contains(overlay_point) {
+                        self.answer_offer(false);
+                    }
+                    return Ok(());
+                }
+
+                // clicks on the side panel, these work on the opponent's turn too
+                if let Some(panel) = self.layout.panel
+                    && panel.contains(Vec2::new(_x, _y)) {
+
+                    if let Some(button) = PanelButton::at(panel, Vec2::new(_x, _y)) {
+                        self.press(button);
+                    } else if let Some(index) = self.move_list().move_at(move_list_area(panel), Vec2::new(_x, _y)) {
+                        self.show_ply(index + 1);
+                    }
+                    return Ok(());
+                }
+
+                // earlier positions are read-only
+                if self.history.is_some() {
+                    return Ok(());
+                }
+
+                if let Some(network_player) = &self.network_player
+                    && network_player.color != self.game.active_player() {
+                    println!("Opponent is to move");
+                    return Ok(());
+                }
+
+                // the game ended since the last update, the popup isn't up yet
+                if self.gameover && !self.show_gameover_popup {
+                    return Ok(());
+                }
+
+                if !self.show_gameover_popup {
+
+                    if let Some(picker) = self.promotion_picker() {
 
-                        self.selected_square = None;
-                        self.selected_target = None;
-                        self.highlight.selected_square = None;
-                        self.promotion = false;
+                        // a click next to the pieces cancels the promotion
+                        match picker.choice_at(Vec2::new(_x, _y)).and_then(|promotion_type| self.input.choose_promotion(promotion_type)) {
+                            Some(mv) => self.submit_move(mv),
+                            None => self.input.cancel(),
+                        }
 
+                    } else if let Some(board_position) = self.board.square_at(Vec2::new(_x, _y))
+                        && let Some(mv) = self.input.press(&self.game, board_position, Vec2::new(_x, _y)) {
+                        self.submit_move(mv);
                     }
 
                 } else {
 
                     // check if they click "restart game"-button
-                    // convert (x,y)-coordinates to GuiPosition
-                    let row = (_y / SQUARE_SIZE).floor() as u8;
-                    let col = (_x / SQUARE_SIZE).floor() as u8;
-                    
-                    let gui_position = BoardPosition {file: U3::try_from(col).unwrap(), rank: U3::try_from(row).unwrap()};
-                    let board_position = inverse_boardpos_guipos(gui_position);
+                    let Some(board_position) = self.board.square_at(Vec2::new(_x, _y)) else {
+                        return Ok(());
+                    };
 
                     let rank = board_position.rank.get(); 
                     let file = board_position.file.get();
@@ -942,7 +1543,7 @@ impl event::EventHandler for GameState {
 
                         (3, 3) | (3, 4) | (4, 3) | (4, 4) => {
 
-                            self.reset(_ctx)?;
+                            self.reset()?;
                             return Ok(());
                         }
                         _ => {}
@@ -961,22 +1562,239 @@ impl event::EventHandler for GameState {
     }
 
 
+
+    fn resize_event(&mut self, _ctx: &mut Context, width: f32, height: f32) -> GameResult {
+
+        self.layout = Layout::new(width, height);
+        self.board.area = self.layout.board;
+
+        Ok(())
+    }
+
+    fn mouse_motion_event(&mut self, _ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) -> GameResult {
+
+        self.input.motion(Vec2::new(x, y));
+
+        Ok(())
+    }
+
+    fn mouse_button_up_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) -> GameResult {
+
+        if button != MouseButton::Left {
+            return Ok(());
+        }
+
+        // a piece still held when the game ended or an offer came in is put back
+        if self.gameover || self.offer.is_some() {
+            self.input.cancel();
+            return Ok(());
+        }
+
+        // only does something when a piece was picked up
+        if let Some(mv) = self.input.release(&self.game, self.board.square_at(Vec2::new(x, y))) {
+            self.submit_move(mv);
+            self.animation = None; // it was dropped where it goes
+        }
+
+        Ok(())
+    }
+
+    fn key_down_event(&mut self, ctx: &mut Context, input: KeyInput, _repeated: bool) -> GameResult {
+
+        // Cmd+V/Cmd+S on macOS
+        let command = input.mods.contains(KeyMods::CTRL) || input.mods.contains(KeyMods::LOGO);
+        let paste = input.keycode == Some(KeyCode::V) && command;
+
+        if let Some(fen_dialog) = &mut self.fen_dialog {
+
+            match input.keycode {
+                Some(KeyCode::Escape) => self.fen_dialog = None,
+                Some(KeyCode::Back) => fen_dialog.backspace(),
+                Some(KeyCode::Return) | Some(KeyCode::NumpadEnter) => self.start_from_fen_dialog()?,
+                _ if paste => fen_dialog.paste(),
+                _ => {}
+            }
+            return Ok(());
+        }
+
+        if input.keycode == Some(KeyCode::F) && !command {
+            self.board.flip();
+        }
+
+        if input.keycode == Some(KeyCode::F3) {
+            self.settings.show_frame_time = !self.settings.show_frame_time;
+        }
+
+        if input.keycode == Some(KeyCode::C) && !command {
+            self.settings.show_coordinates = !self.settings.show_coordinates;
+            self.settings.save(ctx);
+        }
+
+        if input.keycode == Some(KeyCode::M) && !command {
+            self.settings.animation_speed = self.settings.animation_speed.next();
+            self.settings.save(ctx);
+            println!("Move animation: {}", self.settings.animation_speed.name());
+        }
+
+        if input.keycode == Some(KeyCode::P) && !command {
+            self.next_piece_set(ctx);
+        }
+
+        if input.keycode == Some(KeyCode::B) && !command {
+            self.next_board_theme(ctx);
+        }
+
+        if let Some(replay) = &mut self.replay {
+
+            match input.keycode {
+                Some(KeyCode::Left) => replay.back(),
+                Some(KeyCode::Right) => replay.forward(),
+                Some(KeyCode::Home) => replay.first(),
+                Some(KeyCode::End) => replay.last(),
+                Some(KeyCode::Escape) => self.replay = None, // back to the game
+                _ => {}
+            }
+            return Ok(());
+        }
+
+        if self.offer.is_some() {
+
+            match input.keycode {
+                Some(KeyCode::Y) => self.answer_offer(true),
+                Some(KeyCode::N) => self.answer_offer(false),
+                _ => {}
+            }
+            return Ok(());
+        }
+
+        // Escape only closes things, the window is closed the usual way
+        if input.keycode == Some(KeyCode::Escape) {
+
+            if self.input.promotion().is_some() {
+                self.input.cancel();
+            }
+            return Ok(());
+        }
+
+        // the move list: Left/Right step, Home to the start, End back to the game
+        if self.lobby.is_none() {
+
+            match input.keycode {
+                Some(KeyCode::Left) => self.show_ply(self.shown_ply().saturating_sub(1)),
+                Some(KeyCode::Right) => self.show_ply(self.shown_ply() + 1),
+                Some(KeyCode::Home) => self.show_ply(0),
+                Some(KeyCode::End) => self.show_ply(self.moves.len()),
+                _ => {}
+            }
+        }
+
+        // Ctrl+Z: undo, or ask for a takeback in network games
+        // Ctrl+Y or Ctrl+Shift+Z: redo (local games only)
+        let undo = input.keycode == Some(KeyCode::Z) && command && !input.mods.contains(KeyMods::SHIFT);
+        let redo = command && (input.keycode == Some(KeyCode::Y) || (input.keycode == Some(KeyCode::Z) && input.mods.contains(KeyMods::SHIFT)));
+
+        if self.lobby.is_none() {
+
+            if undo && self.network_player.is_some() {
+                self.request_takeback();
+            } else if undo {
+                self.undo();
+            } else if redo && self.network_player.is_none() {
+                self.redo();
+            }
+        }
+
+        if input.keycode == Some(KeyCode::S) && command {
+            self.save_pgn();
+        }
+
+        if input.keycode == Some(KeyCode::L) && !command {
+            self.settings.show_legal_moves = !self.settings.show_legal_moves;
+            self.settings.save(ctx);
+        }
+
+        // Ctrl+R resign, Ctrl+D offer a draw, Ctrl+A abort
+        if self.lobby.is_none() && command {
+
+            match input.keycode {
+                Some(KeyCode::R) => self.press(PanelButton::Resign),
+                Some(KeyCode::D) => self.press(PanelButton::OfferDraw),
+                Some(KeyCode::A) => self.press(PanelButton::Abort),
+                _ => {}
+            }
+        }
+
+        if paste {
+            // the position of a network game is agreed on in the handshake
+            if self.network_player.is_some() || self.lobby.is_some() {
+                println!("Can't change the starting position of a network game");
+            } else {
+                self.fen_dialog = Some(FenDialog::new());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) -> GameResult {
+
+        if let Some(fen_dialog) = &mut self.fen_dialog {
+            fen_dialog.type_char(character);
+        }
+
+        Ok(())
+    }
+
 }
 
 
 fn main() -> GameResult {
 
+    let cli = Cli::parse();
+
     let window_setup = ggez::conf::WindowSetup::default().title("Chess");
     let window_mode = ggez::conf::WindowMode::default()
-        .dimensions(WIDTH, HEIGHT); // width & height of frame
+        .dimensions(cli.window_size * (1.0 + layout::PANEL_RATIO), cli.window_size) // width & height of frame, board and side panel
+        .resizable(true)
+        .min_dimensions(320.0, 240.0);
 
     let cb = ggez::ContextBuilder::new("chess", "julina")
         .window_setup(window_setup)
         .window_mode(window_mode)
         .add_resource_path("./resources");
+
+    // checked before anything starts, and passed on as written by fen::write so both sides have the same text
+    let start_fen = match &cli.fen {
+        Some(text) => {
+            let (game, position) = fen::new_game(text)
+                .map_err(|e| ggez::GameError::CustomError(format!("Invalid FEN: {}", e)))?;
+            Some(fen::write(game.board(), &position))
+        }
+        None => None,
+    };
+    let host_fen = start_fen.clone().unwrap_or_else(fen::default_fen);
+
+    let lobby = match cli.mode() {
+        Mode::Local => None,
+        Mode::Host { bind } => Some(Lobby::host(&bind, cli.color.map(PlayerColor::from), host_fen, cli.time_control())),
+        Mode::Join { addr } => {
+            if cli.color.is_some() || cli.fen.is_some() || cli.time.is_some() {
+                println!("Ignoring --color, --fen and --time, the host decides who plays which color, where the game starts and the time control");
+            }
+            Some(Lobby::join(&addr))
+        }
+        Mode::Auto { addr } => Some(Lobby::auto(&addr, host_fen, cli.time_control())),
+    };
+
     let (mut ctx, event_loop) = cb.build()?;
-    let state = GameState::new(&mut ctx, true, ADDR)?;
-    event::run(ctx, event_loop, state);
+    let mut state = GameState::new(&mut ctx, lobby, start_fen, cli.time_control())?;
+
+    if let Some(path) = &cli.pgn {
+        // same as dropping the file onto the window
+        state.open_replay(path);
+    }
+
+    event_loop::run(ctx, event_loop, state);
 
 
 }
\ No newline at end of file
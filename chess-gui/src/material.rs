@@ -0,0 +1,174 @@
+// captured pieces and who is ahead in material, shown next to the clocks
+//
+// what a move captured is found by comparing the board before and after it, so en passant
+// and promotions need no special cases. the material difference is counted on the board
+// itself (pawn 1, knight and bishop 3, rook 5, queen 9): a promoted pawn counts as what it became.
+
+use ggez::graphics::{self, Canvas, Color, DrawParam, Rect};
+use ggez::glam::*;
+use leben_chess::board::Board;
+use leben_chess::board::piece::{Piece, PieceType, PlayerColor};
+
+use crate::atlas::{PieceAtlas, PIECE_TYPES};
+use crate::{fen, opponent_color};
+use crate::san::PlayedMove;
+
+
+pub fn value(piece_type: PieceType) -> i32 {
+
+    match piece_type {
+        PieceType::Pawn => 1,
+        PieceType::Knight | PieceType::Bishop => 3,
+        PieceType::Rook => 5,
+        PieceType::Queen => 9,
+        PieceType::King => 0,
+    }
+}
+
+fn pieces(board: &Board) -> impl Iterator<Item = Piece> + '_ {
+
+    (0..8u8)
+        .flat_map(|rank| (0..8u8).map(move |file| (file, rank)))
+        .filter_map(|(file, rank)| board.get_piece(fen::square(file, rank)))
+}
+
+// how many pieces of each kind are on a board, taken before a move to compare with after it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PieceCount([[usize; 6]; 2]); // [color][index in PIECE_TYPES]
+
+impl PieceCount {
+
+    pub fn of(board: &Board) -> Self {
+
+        let mut count = [[0; 6]; 2];
+
+        for piece in pieces(board) {
+            count[color_index(piece.player)][type_index(piece.piece_type)] += 1;
+        }
+
+        PieceCount(count)
+    }
+
+}
+
+fn color_index(color: PlayerColor) -> usize {
+
+    match color {
+        PlayerColor::White => 0,
+        PlayerColor::Black => 1,
+    }
+}
+
+fn type_index(piece_type: PieceType) -> usize {
+    PIECE_TYPES.iter().position(|&other| other == piece_type).unwrap()
+}
+
+// the opponent's piece that is gone after a move by mover
+pub fn captured(before: &PieceCount, after: &PieceCount, mover: PlayerColor) -> Option<Piece> {
+
+    let victim = opponent_color(mover);
+    let (before, after) = (before.0[color_index(victim)], after.0[color_index(victim)]);
+
+    PIECE_TYPES
+        .into_iter()
+        .find(|&piece_type| after[type_index(piece_type)] < before[type_index(piece_type)])
+        .map(|piece_type| Piece { piece_type, player: victim })
+}
+
+// positive when White is ahead
+pub fn balance(board: &Board) -> i32 {
+
+    pieces(board)
+        .map(|piece| match piece.player {
+            PlayerColor::White => value(piece.piece_type),
+            PlayerColor::Black => -value(piece.piece_type),
+        })
+        .sum()
+}
+
+// the pieces color took, the most valuable first
+pub fn captured_by(moves: &[PlayedMove], color: PlayerColor) -> Vec<Piece> {
+
+    let mut taken: Vec<Piece> = moves
+        .iter()
+        .filter_map(|played| played.captured)
+        .filter(|piece| piece.player != color)
+        .collect();
+
+    taken.sort_by_key(|piece| -value(piece.piece_type));
+    taken
+}
+
+// a row of small pieces, overlapping a bit, then "+3" if color is ahead
+pub fn draw_captures(canvas: &mut Canvas, atlas: &PieceAtlas, area: Rect, taken: &[Piece], color: PlayerColor, balance: i32) {
+
+    let size = area.h;
+    let mut x = area.x;
+
+    for (i, piece) in taken.iter().enumerate() {
+
+        // pieces of the same kind are packed closer together
+        if i > 0 {
+            x += if taken[i - 1].piece_type == piece.piece_type { size*0.4 } else { size*0.7 };
+        }
+        atlas.draw_piece(canvas, *piece, Vec2::new(x, area.y), size);
+    }
+
+    let ahead = match color {
+        PlayerColor::White => balance,
+        PlayerColor::Black => -balance,
+    };
+
+    if ahead > 0 {
+
+        let mut text = graphics::Text::new(format!("+{}", ahead));
+        text.set_scale(size*0.6);
+        let x = if taken.is_empty() { area.x } else { x + size*1.1 };
+        canvas.draw(&text, DrawParam::default().dest([x, area.y + size*0.2]).color(Color::from_rgb(200, 200, 200)));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn board(fen: &str) -> Board {
+        fen::parse(fen).unwrap().0
+    }
+
+    fn captured_between(before: &str, after: &str, mover: PlayerColor) -> Option<Piece> {
+        captured(&PieceCount::of(&board(before)), &PieceCount::of(&board(after)), mover)
+    }
+
+    #[test]
+    fn finds_the_captured_piece() {
+
+        let before = "4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1";
+        let after = "4k3/8/8/3P4/8/8/8/4K3 b - - 0 1";
+
+        assert!(captured_between(before, after, PlayerColor::White).is_some_and(|piece| piece.piece_type == PieceType::Pawn && piece.player == PlayerColor::Black));
+        assert!(captured_between(after, after, PlayerColor::Black).is_none());
+    }
+
+    #[test]
+    fn en_passant_and_promotion_are_board_diffs_too() {
+
+        // en passant: the captured pawn isn't on the target square
+        let captured = captured_between("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "4k3/8/3P4/8/8/8/8/4K3 b - - 0 1", PlayerColor::White);
+        assert!(captured.is_some_and(|piece| piece.piece_type == PieceType::Pawn));
+
+        // promotion without a capture: our pawn turning into a queen takes nothing
+        assert!(captured_between("4k3/P7/8/8/8/8/8/4K3 w - - 0 1", "Q3k3/8/8/8/8/8/8/4K3 b - - 0 1", PlayerColor::White).is_none());
+    }
+
+    #[test]
+    fn balance_counts_the_board() {
+
+        assert_eq!(balance(&board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")), 0);
+        assert_eq!(balance(&board("4k3/8/8/8/8/8/8/R3K3 w - - 0 1")), 5);
+        assert_eq!(balance(&board("q3k3/8/8/8/8/8/8/R3K3 w - - 0 1")), -4);
+    }
+
+}
@@ -0,0 +1,245 @@
+// the moves of the game in SAN, in the side panel, a row per move number:
+//
+//   1.  e4     e5
+//   2.  Nf3    Nc6
+//
+// a game started from a FEN with Black to move begins with an empty White column.
+// the move that led to the shown position is highlighted, clicking a move shows the
+// position after it (read-only), see GameState::show_ply.
+// Left/Right step through the moves, Home jumps to the start, End back to the game.
+
+use ggez::graphics::{self, Canvas, Color, DrawParam, Rect};
+use ggez::{Context, GameResult};
+use ggez::glam::*;
+use leben_chess::board::piece::PlayerColor;
+
+use crate::fen::PositionInfo;
+use crate::san::PlayedMove;
+
+
+pub struct MoveList<'a> {
+    moves: &'a [PlayedMove],
+    first_number: u32, // move number of the first row
+    black_first: bool, // the game started with Black to move
+    shown: usize, // number of moves played on the shown board
+}
+
+impl<'a> MoveList<'a> {
+
+    pub fn new(moves: &'a [PlayedMove], start: &PositionInfo, shown: usize) -> Self {
+        MoveList {
+            moves,
+            first_number: start.fullmove_number,
+            black_first: start.active_player == PlayerColor::Black,
+            shown,
+        }
+    }
+
+    // (row, column) of a move, column 0 is White's
+    fn cell(&self, index: usize) -> (usize, usize) {
+
+        let slot = index + self.black_first as usize;
+        (slot / 2, slot % 2)
+    }
+
+    fn index_at(&self, row: usize, column: usize) -> Option<usize> {
+
+        (row*2 + column)
+            .checked_sub(self.black_first as usize)
+            .filter(|&index| index < self.moves.len())
+    }
+
+    fn row_count(&self) -> usize {
+        (self.moves.len() + self.black_first as usize).div_ceil(2)
+    }
+
+    fn row_height(area: Rect) -> f32 {
+        area.w*0.1
+    }
+
+    // the row with the shown move, if it's not the start position
+    fn current_row(&self) -> Option<usize> {
+        self.shown.checked_sub(1).map(|index| self.cell(index).0)
+    }
+
+    fn is_live(&self) -> bool {
+        self.shown == self.moves.len()
+    }
+
+    // rows that fit, one less while an earlier position is shown, for the hint
+    fn visible_rows(&self, area: Rect) -> usize {
+
+        let rows = (area.h / MoveList::row_height(area)).floor() as usize;
+        if self.is_live() { rows } else { rows.saturating_sub(1) }
+    }
+
+    // long games scroll, keeping the shown move in view
+    fn first_row(&self, visible: usize) -> usize {
+
+        let rows = self.row_count();
+
+        if rows <= visible {
+            return 0;
+        }
+
+        let current = self.current_row().unwrap_or(0);
+        current.saturating_sub(visible / 2).min(rows - visible)
+    }
+
+    // x and width of a column: the move number, White's move, Black's move
+    fn column(area: Rect, column: usize) -> (f32, f32) {
+
+        match column {
+            0 => (area.x + area.w*0.2, area.w*0.4),
+            1 => (area.x + area.w*0.6, area.w*0.4),
+            _ => (area.x, area.w*0.2),
+        }
+    }
+
+    // the index of the move under a point in window pixels
+    pub fn move_at(&self, area: Rect, point: Vec2) -> Option<usize> {
+
+        if !area.contains(point) {
+            return None;
+        }
+
+        let visible = self.visible_rows(area);
+        let row = ((point.y - area.y) / MoveList::row_height(area)).floor() as usize;
+
+        if row >= visible {
+            return None;
+        }
+
+        let column = (0..2).find(|&column| {
+            let (x, w) = MoveList::column(area, column);
+            point.x >= x && point.x < x + w
+        })?;
+
+        self.index_at(self.first_row(visible) + row, column)
+    }
+
+    pub fn draw(&self, ctx: &mut Context, canvas: &mut Canvas, area: Rect) -> GameResult {
+
+        let row_height = MoveList::row_height(area);
+        let visible = self.visible_rows(area);
+        let first_row = self.first_row(visible);
+        let text_scale = row_height*0.6;
+
+        let highlight = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(0.0, 0.0, area.w*0.4, row_height),
+            Color::from_rgb(70, 70, 90),
+        )?;
+
+        for row in first_row..(first_row + visible).min(self.row_count()) {
+
+            let y = area.y + (row - first_row) as f32*row_height;
+
+            let mut number = graphics::Text::new(format!("{}.", self.first_number as usize + row));
+            number.set_scale(text_scale);
+            let (x, _) = MoveList::column(area, 2);
+            canvas.draw(&number, DrawParam::default().dest([x + row_height*0.2, y + row_height*0.2]).color(Color::from_rgb(150, 150, 150)));
+
+            for column in 0..2 {
+
+                let Some(index) = self.index_at(row, column) else {
+                    continue;
+                };
+                let (x, _) = MoveList::column(area, column);
+
+                if index + 1 == self.shown {
+                    canvas.draw(&highlight, Vec2::new(x, y));
+                }
+
+                let mut san = graphics::Text::new(self.moves[index].san.as_str());
+                san.set_scale(text_scale);
+                canvas.draw(&san, DrawParam::default().dest([x + row_height*0.2, y + row_height*0.2]));
+            }
+        }
+
+        if !self.is_live() {
+
+            let mut hint = graphics::Text::new("End: back to the game");
+            hint.set_scale(text_scale*0.9);
+            let y = area.y + visible as f32*row_height;
+            canvas.draw(&hint, DrawParam::default().dest([area.x + row_height*0.2, y + row_height*0.2]).color(Color::from_rgb(255, 200, 80)));
+        }
+
+        Ok(())
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use leben_chess::moves::{ChessMove, PieceMovement};
+    use crate::fen::parse_square;
+
+    fn moves(count: usize) -> Vec<PlayedMove> {
+
+        let mv = ChessMove {
+            piece_movement: PieceMovement { from: parse_square("e2").unwrap(), to: parse_square("e4").unwrap() },
+            promotion: None,
+        };
+        (0..count).map(|i| PlayedMove { mv, san: format!("m{}", i), captured: None }).collect()
+    }
+
+    #[test]
+    fn two_moves_per_row() {
+
+        let moves = moves(3);
+        let list = MoveList::new(&moves, &PositionInfo::starting(), 3);
+
+        assert_eq!(list.row_count(), 2);
+        assert_eq!(list.cell(2), (1, 0));
+        assert_eq!(list.index_at(0, 1), Some(1));
+        assert_eq!(list.index_at(1, 1), None);
+    }
+
+    #[test]
+    fn black_to_move_leaves_the_first_white_move_empty() {
+
+        let moves = moves(2);
+        let mut start = PositionInfo::starting();
+        start.active_player = PlayerColor::Black;
+        let list = MoveList::new(&moves, &start, 2);
+
+        assert_eq!(list.index_at(0, 0), None);
+        assert_eq!(list.index_at(0, 1), Some(0));
+        assert_eq!(list.index_at(1, 0), Some(1));
+        assert_eq!(list.row_count(), 2);
+    }
+
+    #[test]
+    fn clicks_find_the_move() {
+
+        let moves = moves(4);
+        let list = MoveList::new(&moves, &PositionInfo::starting(), 4);
+        let area = Rect::new(0.0, 0.0, 100.0, 200.0); // rows are 10 high
+
+        assert_eq!(list.move_at(area, Vec2::new(30.0, 5.0)), Some(0));
+        assert_eq!(list.move_at(area, Vec2::new(70.0, 15.0)), Some(3));
+        assert_eq!(list.move_at(area, Vec2::new(10.0, 5.0)), None); // the move number
+        assert_eq!(list.move_at(area, Vec2::new(30.0, 25.0)), None); // below the last row
+    }
+
+    #[test]
+    fn long_games_scroll_to_the_shown_move() {
+
+        let moves = moves(100);
+
+        let live = MoveList::new(&moves, &PositionInfo::starting(), 100);
+        assert_eq!(live.first_row(20), 30);
+
+        let start = MoveList::new(&moves, &PositionInfo::starting(), 1);
+        assert_eq!(start.first_row(20), 0);
+
+        let middle = MoveList::new(&moves, &PositionInfo::starting(), 51);
+        assert_eq!(middle.first_row(20), 15);
+    }
+
+}
@@ -0,0 +1,343 @@
+// networking: TCP connection to the opponent
+
+use std::time::Duration;
+
+use leben_chess::board::piece::PlayerColor;
+use leben_chess::chess::ChessGame;
+use leben_chess::moves::ChessMove;
+
+use crate::{fen, opponent_color};
+use crate::clock::TimeControl;
+use crate::protocol::{ProtocolMessage, ProtocolError, Outcome};
+
+// tcp imports
+
+use std::io;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+
+pub const MSG_SIZE: usize = 128;
+
+
+pub fn random_color() -> PlayerColor {
+    if rand::random() { PlayerColor::White } else { PlayerColor::Black }
+}
+
+// the stream is always a TcpStream in the game, the tests read from and write to memory
+pub struct NetworkPlayer<S = TcpStream> {
+    stream: S,
+    pub color: PlayerColor, // color of the local player
+    pub start_fen: String, // position the game starts from, picked by the host
+    pub time_control: Option<TimeControl>, // also picked by the host
+    frame_reader: FrameReader,
+    outgoing: Vec<u8>, // encoded frames the socket hasn't taken yet
+    disconnected: Option<String>,
+}
+
+pub enum NetworkEvent {
+    Message(ProtocolMessage),
+    Invalid(ProtocolError), // a complete frame arrived, but it could not be decoded
+    Disconnected(String),
+}
+
+// the stream is non-blocking, so a message can arrive in several pieces spread
+// over multiple update() calls. bytes are buffered here until a whole frame is in.
+struct FrameReader {
+    buffer: Vec<u8>,
+}
+
+impl FrameReader {
+
+    fn new() -> Self {
+        FrameReader { buffer: Vec::with_capacity(MSG_SIZE) }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    fn next_frame(&mut self) -> Option<[u8; MSG_SIZE]> {
+
+        if self.buffer.len() < MSG_SIZE {
+            return None;
+        }
+
+        let mut frame = [0; MSG_SIZE];
+        frame.copy_from_slice(&self.buffer[..MSG_SIZE]);
+        self.buffer.drain(..MSG_SIZE);
+
+        Some(frame)
+    }
+}
+
+impl NetworkPlayer {
+
+    pub fn accept(stream: TcpStream, color: PlayerColor, start_fen: String, time_control: Option<TimeControl>) -> io::Result<Self> { // server side of the handshake
+
+        // accepted streams can inherit non-blocking mode from the listener
+        stream.set_nonblocking(false)?;
+
+        let mut network_player = NetworkPlayer::new(stream, color, start_fen, time_control);
+
+        // tell the client which color they play, where the game starts and how much time there is
+        let handshake = ProtocolMessage::Init {
+            client_color: opponent_color(color),
+            fen: network_player.start_fen.clone(),
+            time_control,
+        };
+        let frame = handshake.encode().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        network_player.stream.write_all(&frame)?;
+
+        network_player.stream.set_nonblocking(true)?;
+        Ok(network_player)
+    }
+
+    pub fn join(addr: &str) -> io::Result<Self> {
+
+        let mut stream = TcpStream::connect(addr)?;
+        println!("Connected to {} as CLIENT", addr);
+
+        // wait for the host to assign our color and starting position (still blocking here)
+        let mut msg_buf = [0; MSG_SIZE];
+        stream.read_exact(&mut msg_buf)?;
+
+        let (color, start_fen, time_control) = match ProtocolMessage::decode(&msg_buf) {
+            Ok(ProtocolMessage::Init { client_color, fen, time_control }) => (client_color, fen, time_control),
+            Ok(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "Expected handshake from host")),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+
+        // refuse positions we can't play before the game starts, rather than halfway through
+        fen::new_game(&start_fen).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Host sent an invalid FEN: {}", e)))?;
+
+        println!("Host assigned us {:?}, starting from {}", color, start_fen);
+
+        stream.set_nonblocking(true)?;
+        Ok(NetworkPlayer::new(stream, color, start_fen, time_control))
+    }
+
+    pub fn move_message(game: &ChessGame, fen: String, mv: ChessMove, clock: Option<(Duration, Duration)>) -> ProtocolMessage {
+
+        // sent after performing mv on game, fen is the full position afterwards
+        ProtocolMessage::Move {
+            mv,
+            status: Outcome::from(game.game_status()),
+            fen,
+            clock,
+        }
+    }
+
+}
+
+impl<S: Read + Write> NetworkPlayer<S> {
+
+    fn new(stream: S, color: PlayerColor, start_fen: String, time_control: Option<TimeControl>) -> Self {
+        NetworkPlayer {stream, color, start_fen, time_control, frame_reader: FrameReader::new(), outgoing: Vec::new(), disconnected: None}
+    }
+
+    // see protocol.rs for the message format
+
+    pub fn read_tcp_message(&mut self) -> Option<NetworkEvent> {
+
+        // pull in whatever has arrived, then hand out complete frames one by one
+        self.receive();
+
+        if let Some(frame) = self.frame_reader.next_frame() {
+            return match ProtocolMessage::decode(&frame) {
+                Ok(msg) => Some(NetworkEvent::Message(msg)),
+                Err(e) => Some(NetworkEvent::Invalid(e)),
+            };
+        }
+
+        // only report the disconnect once every frame sent before it has been handled
+        self.disconnected.clone().map(NetworkEvent::Disconnected)
+    }
+
+    fn receive(&mut self) {
+
+        if self.disconnected.is_some() {
+            return;
+        }
+
+        let mut read_buf = [0; 4 * MSG_SIZE];
+
+        loop {
+            match self.stream.read(&mut read_buf) {
+                Ok(0) => {
+                    self.disconnected = Some("Opponent disconnected".to_string());
+                    return;
+                }
+                Ok(n) => self.frame_reader.push(&read_buf[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.disconnected = Some(format!("Connection lost: {}", e));
+                    return;
+                }
+            }
+        }
+    }
+
+    // queued, the socket is non-blocking and might take only part of a frame.
+    // whatever doesn't fit now is sent by flush() on later updates
+    // a message that can't be sent would leave the two sides disagreeing, so that ends the game
+    pub fn write_tcp_message(&mut self, msg: &ProtocolMessage) {
+
+        match msg.encode() {
+            Ok(frame) => self.outgoing.extend_from_slice(&frame),
+            Err(e) => { self.disconnected.get_or_insert_with(|| format!("Could not send message: {}", e)); }
+        }
+        self.flush();
+    }
+
+    pub fn flush(&mut self) {
+
+        if self.disconnected.is_some() {
+            self.outgoing.clear();
+            return;
+        }
+
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => {
+                    self.disconnected = Some("Connection lost while sending".to_string());
+                    return;
+                }
+                Ok(n) => { self.outgoing.drain(..n); }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.disconnected = Some(format!("Connection lost: {}", e));
+                    return;
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    // hands out one queued chunk per read and WouldBlock when there is none,
+    // takes `writable` more bytes before writes block
+    struct MemoryStream {
+        reads: VecDeque<Vec<u8>>,
+        written: Vec<u8>,
+        writable: usize,
+    }
+
+    impl Read for MemoryStream {
+
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+
+            let Some(chunk) = self.reads.pop_front() else {
+                return Err(io::ErrorKind::WouldBlock.into());
+            };
+
+            buf[..chunk.len()].copy_from_slice(&chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    impl Write for MemoryStream {
+
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+
+            if self.writable == 0 {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+
+            let n = buf.len().min(self.writable);
+            self.written.extend_from_slice(&buf[..n]);
+            self.writable -= n;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn player(reads: Vec<Vec<u8>>, writable: usize) -> NetworkPlayer<MemoryStream> {
+        let stream = MemoryStream { reads: reads.into(), written: Vec::new(), writable };
+        NetworkPlayer::new(stream, PlayerColor::White, fen::default_fen().to_string(), None)
+    }
+
+    fn resign() -> Vec<u8> {
+        ProtocolMessage::Resign.encode().unwrap().to_vec()
+    }
+
+    #[test]
+    fn frame_split_across_reads() {
+
+        let frame = resign();
+        let mut player = player(vec![frame[..50].to_vec()], 0);
+
+        assert!(player.read_tcp_message().is_none());
+
+        player.stream.reads.push_back(frame[50..].to_vec());
+        assert!(matches!(player.read_tcp_message(), Some(NetworkEvent::Message(ProtocolMessage::Resign))));
+        assert!(player.read_tcp_message().is_none());
+    }
+
+    #[test]
+    fn two_frames_in_one_read() {
+
+        let frames = [ProtocolMessage::DrawOffer.encode().unwrap(), ProtocolMessage::Abort.encode().unwrap()].concat();
+        let mut player = player(vec![frames], 0);
+
+        assert!(matches!(player.read_tcp_message(), Some(NetworkEvent::Message(ProtocolMessage::DrawOffer))));
+        assert!(matches!(player.read_tcp_message(), Some(NetworkEvent::Message(ProtocolMessage::Abort))));
+        assert!(player.read_tcp_message().is_none());
+    }
+
+    #[test]
+    fn empty_read_is_a_disconnect() {
+
+        // the frame that came before still gets through
+        let mut player = player(vec![resign(), Vec::new()], 0);
+
+        assert!(matches!(player.read_tcp_message(), Some(NetworkEvent::Message(ProtocolMessage::Resign))));
+        assert!(matches!(player.read_tcp_message(), Some(NetworkEvent::Disconnected(_))));
+        assert!(matches!(player.read_tcp_message(), Some(NetworkEvent::Disconnected(_))));
+    }
+
+    #[test]
+    fn malformed_frame_is_invalid() {
+
+        let mut frame = b"ChessNOPE:".to_vec();
+        frame.resize(MSG_SIZE, b'0');
+        let mut player = player(vec![frame], 0);
+
+        assert!(matches!(
+            player.read_tcp_message(),
+            Some(NetworkEvent::Invalid(ProtocolError::UnknownMessage(id))) if id == "ChessNOPE"
+        ));
+    }
+
+    #[test]
+    fn blocked_writes_stay_queued() {
+
+        let mut player = player(Vec::new(), 100);
+
+        player.write_tcp_message(&ProtocolMessage::Resign);
+        assert_eq!(player.stream.written.len(), 100);
+        assert_eq!(player.outgoing.len(), MSG_SIZE - 100);
+
+        // still blocked
+        player.flush();
+        assert_eq!(player.stream.written.len(), 100);
+
+        player.stream.writable = usize::MAX;
+        player.flush();
+        assert_eq!(player.stream.written, resign());
+        assert!(player.outgoing.is_empty());
+    }
+
+}
@@ -0,0 +1,67 @@
+// prompt for answering an offer: a takeback or draw from the opponent over the network,
+// or a draw offered by the other player at the same machine
+//
+// accepted with the Accept button or Y, declined with the Decline button or N
+
+use ggez::graphics::{self, Canvas, Color, DrawParam, Rect};
+use ggez::{Context, GameResult};
+use ggez::glam::*;
+use leben_chess::board::piece::PlayerColor;
+
+use crate::SQUARE_SIZE;
+use crate::button::draw_button;
+
+
+pub enum Offer {
+    // ply: number of half-moves the game has left after the takeback
+    Takeback { ply: usize },
+    Draw { from: PlayerColor },
+}
+
+impl Offer {
+
+    fn question(&self) -> String {
+
+        match self {
+            Offer::Takeback { .. } => "Your opponent asks\nfor a takeback".to_string(),
+            Offer::Draw { from } => format!("{:?} offers\na draw", from),
+        }
+    }
+
+    fn area() -> Rect {
+        Rect::new(SQUARE_SIZE*2.0, SQUARE_SIZE*2.5, SQUARE_SIZE*4.0, SQUARE_SIZE*3.0)
+    }
+
+    pub fn accept_button() -> Rect {
+        let area = Offer::area();
+        Rect::new(area.x + SQUARE_SIZE*0.25, area.y + SQUARE_SIZE*2.0, SQUARE_SIZE*1.6, SQUARE_SIZE*0.75)
+    }
+
+    pub fn decline_button() -> Rect {
+        let area = Offer::area();
+        Rect::new(area.x + SQUARE_SIZE*2.15, area.y + SQUARE_SIZE*2.0, SQUARE_SIZE*1.6, SQUARE_SIZE*0.75)
+    }
+
+    pub fn draw(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+
+        let area = Offer::area();
+
+        let overlay = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(0.0, 0.0, area.w, area.h),
+            Color::from_rgba(0, 0, 60, 220),
+        )?;
+        canvas.draw(&overlay, Vec2::new(area.x, area.y));
+
+        let mut text = graphics::Text::new(self.question());
+        text.set_scale(48.0);
+        canvas.draw(&text, DrawParam::default().dest([area.x + 24.0, area.y + 24.0]));
+
+        draw_button(ctx, canvas, Offer::accept_button(), "Accept (Y)", Color::from_rgb(0, 110, 0))?;
+        draw_button(ctx, canvas, Offer::decline_button(), "Decline (N)", Color::from_rgb(120, 0, 0))?;
+
+        Ok(())
+    }
+
+}
@@ -0,0 +1,553 @@
+// PGN export: Seven Tag Roster + movetext, saved to the games/ directory
+//
+// SetUp/FEN tags are added when the game didn't start from the initial position,
+// Termination says how the game ended in the standard's words (normal, time forfeit, ...),
+// and for games ended by the rules a comment after the moves gives the chess lib's reason
+//
+// PGN import: tags and the SAN moves of the main line, comments, variations
+// and annotation glyphs are skipped. Only the first game of a file is read.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use leben_chess::board::piece::PlayerColor;
+use leben_chess::chess::GameStatus;
+
+use crate::fen::{FenError, PositionInfo};
+use crate::protocol::Outcome;
+use crate::san::PlayedMove;
+
+
+const SAVE_DIR: &str = "games";
+const LINE_LENGTH: usize = 80; // PGN export format limit
+
+pub struct PgnGame<'a> {
+    pub event: String,
+    pub site: String,
+    pub white: String,
+    pub black: String,
+    pub outcome: Outcome,
+    pub termination: Option<Termination>,
+    pub start_fen: Option<&'a str>,
+    pub start_position: PositionInfo,
+    pub moves: &'a [PlayedMove],
+}
+
+// a game read from a PGN file, the moves are not checked yet
+pub struct PgnFile {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PgnError {
+    Io(String),
+    InvalidTag(String),
+    InvalidFen(FenError),
+    // number is the move number as written in PGN, e.g. "12." or "12..."
+    IllegalMove { number: String, san: String, reason: String },
+}
+
+impl fmt::Display for PgnError {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PgnError::Io(e) => write!(f, "could not read file: {}", e),
+            PgnError::InvalidTag(line) => write!(f, "invalid tag {:?}", line),
+            PgnError::InvalidFen(e) => write!(f, "invalid FEN tag: {}", e),
+            PgnError::IllegalMove { number, san, reason } => write!(f, "illegal move {} {}: {}", number, san, reason),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+pub fn move_number(fullmove_number: u32, color: PlayerColor) -> String {
+
+    match color {
+        PlayerColor::White => format!("{}.", fullmove_number),
+        PlayerColor::Black => format!("{}...", fullmove_number),
+    }
+}
+
+pub fn result_token(outcome: Outcome) -> &'static str {
+
+    match outcome {
+        Outcome::WhiteWon => "1-0",
+        Outcome::BlackWon => "0-1",
+        Outcome::Draw => "1/2-1/2",
+        Outcome::Ongoing => "*",
+    }
+}
+
+// the values of the Termination tag we use
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Termination {
+    Normal(String), // checkmate, resignation, ... the reason is written as a comment
+    TimeForfeit,
+    Abandoned, // disconnected or aborted
+    RulesInfraction, // the opponent sent a move that isn't legal here
+}
+
+impl Termination {
+
+    pub fn tag(&self) -> &'static str {
+
+        match self {
+            Termination::Normal(_) => "normal",
+            Termination::TimeForfeit => "time forfeit",
+            Termination::Abandoned => "abandoned",
+            Termination::RulesInfraction => "rules infraction",
+        }
+    }
+}
+
+// games that ended by the rules of chess, None while they go on
+pub fn termination(status: &GameStatus) -> Option<Termination> {
+
+    match status {
+        GameStatus::Win(_, reason) => Some(Termination::Normal(format!("{:?}", reason))),
+        GameStatus::Draw(reason) => Some(Termination::Normal(format!("{:?}", reason))),
+        _ => None,
+    }
+}
+
+// tag values are quoted, so quotes and backslashes have to be escaped.
+// a tag has to stay on its line, line breaks and other control characters become spaces
+fn escape(value: &str) -> String {
+
+    value
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect::<String>()
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+}
+
+// days since 1970-01-01 -> (year, month, day), see http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe/1460 + doe/36524 - doe/146096) / 365;
+    let doy = doe - (365*yoe + yoe/4 - yoe/100);
+    let mp = (5*doy + 2) / 153;
+    let day = (doy - (153*mp + 2)/5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era*400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn today() -> String {
+    let (year, month, day) = civil_from_days((now() / 86400) as i64);
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+impl PgnGame<'_> {
+
+    pub fn write(&self) -> String {
+
+        let result = result_token(self.outcome);
+
+        let mut tags = vec![
+            ("Event", self.event.clone()),
+            ("Site", self.site.clone()),
+            ("Date", today()),
+            ("Round", "-".to_string()),
+            ("White", self.white.clone()),
+            ("Black", self.black.clone()),
+            ("Result", result.to_string()),
+        ];
+
+        if let Some(fen) = self.start_fen {
+            tags.push(("SetUp", "1".to_string()));
+            tags.push(("FEN", fen.to_string()));
+        }
+
+        if let Some(termination) = &self.termination {
+            tags.push(("Termination", termination.tag().to_string()));
+        }
+
+        let mut pgn = String::new();
+
+        for (name, value) in tags {
+            pgn += &format!("[{} \"{}\"]\n", name, escape(&value));
+        }
+        pgn.push('\n');
+
+        // movetext, wrapped so that no line gets longer than LINE_LENGTH
+        let mut tokens = Vec::new();
+        let mut fullmove_number = self.start_position.fullmove_number;
+        let mut color = self.start_position.active_player;
+
+        for (i, played) in self.moves.iter().enumerate() {
+
+            if color == PlayerColor::White || i == 0 {
+                tokens.push(move_number(fullmove_number, color));
+            }
+            tokens.push(played.san.clone());
+
+            if color == PlayerColor::Black {
+                fullmove_number += 1;
+            }
+            color = crate::opponent_color(color);
+        }
+
+        // the standard tag only says "normal", the comment says what happened
+        if let Some(Termination::Normal(reason)) = &self.termination {
+            tokens.push(format!("{{{}}}", reason.replace('}', ")")));
+        }
+        tokens.push(result.to_string());
+
+        let mut line = String::new();
+
+        for token in tokens {
+
+            if !line.is_empty() && line.len() + 1 + token.len() > LINE_LENGTH {
+                pgn += &line;
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line += &token;
+        }
+        pgn += &line;
+        pgn.push('\n');
+
+        pgn
+    }
+
+    // writes the game to games/game-<date>-<time>.pgn and returns the path
+    pub fn save(&self) -> io::Result<PathBuf> {
+        self.save_in(Path::new(SAVE_DIR))
+    }
+
+    // games saved within the same second get a counter: game-<date>-<time>-2.pgn, ...
+    fn save_in(&self, dir: &Path) -> io::Result<PathBuf> {
+
+        fs::create_dir_all(dir)?;
+
+        let seconds = now();
+        let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+        let time = seconds % 86400;
+
+        let stem = format!(
+            "game-{:04}{:02}{:02}-{:02}{:02}{:02}",
+            year, month, day, time / 3600, time / 60 % 60, time % 60,
+        );
+        let pgn = self.write();
+
+        let mut count = 1;
+        loop {
+
+            let name = match count {
+                1 => format!("{}.pgn", stem),
+                _ => format!("{}-{}.pgn", stem, count),
+            };
+            let path = dir.join(name);
+
+            // create_new fails instead of overwriting, also if another save got there first
+            match fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    file.write_all(pgn.as_bytes())?;
+                    return Ok(path);
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => count += 1,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+}
+
+impl PgnFile {
+
+    pub fn load(path: &Path) -> Result<Self, PgnError> {
+
+        let text = fs::read_to_string(path).map_err(|e| PgnError::Io(e.to_string()))?;
+        PgnFile::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, PgnError> {
+
+        let mut tags = Vec::new();
+        let mut movetext = String::new();
+
+        for line in text.lines() {
+
+            let line = line.trim();
+
+            // the tag section ends at the first line that isn't a tag
+            if movetext.is_empty() && line.starts_with('[') {
+                tags.push(parse_tag(line).ok_or_else(|| PgnError::InvalidTag(line.to_string()))?);
+                continue;
+            }
+
+            if movetext.is_empty() && line.is_empty() {
+                continue;
+            }
+
+            // an empty line after the movetext, or the next game's tags: first game is done
+            if !movetext.is_empty() && (line.is_empty() || line.starts_with('[')) {
+                break;
+            }
+
+            movetext += line;
+            movetext.push('\n');
+        }
+
+        Ok(PgnFile { tags, moves: parse_movetext(&movetext) })
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+
+}
+
+// [Name "value"]
+fn parse_tag(line: &str) -> Option<(String, String)> {
+
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?.trim();
+    let (name, value) = inner.split_once(char::is_whitespace)?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.push(chars.next()?),
+            c => unescaped.push(c),
+        }
+    }
+
+    Some((name.to_string(), unescaped))
+}
+
+fn finish_token(token: &mut String, moves: &mut Vec<String>) {
+
+    // "12." / "12..." move numbers can be glued to the move, "12.e4", or be split up
+    // by spaces, "12 ... e5" (careful, "0-0" is castling and not a move number)
+    let san = match token.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) if token[i..].starts_with('.') => token[i..].trim_start_matches('.'),
+        Some(_) => token.as_str(),
+        None => "",
+    };
+
+    let is_result = matches!(token.as_str(), "1-0" | "0-1" | "1/2-1/2" | "*");
+    if !san.is_empty() && !is_result && !san.starts_with('$') {
+        moves.push(san.to_string());
+    }
+    token.clear();
+}
+
+// the SAN moves of the main line
+fn parse_movetext(movetext: &str) -> Vec<String> {
+
+    let mut moves = Vec::new();
+    let mut token = String::new();
+    let mut variation_depth: u32 = 0;
+    let mut chars = movetext.chars();
+
+    while let Some(c) = chars.next() {
+
+        match c {
+            // comments
+            '{' => { chars.by_ref().find(|&c| c == '}'); }
+            ';' => { chars.by_ref().find(|&c| c == '\n'); }
+            // variations, which can be nested
+            '(' => variation_depth += 1,
+            ')' => variation_depth = variation_depth.saturating_sub(1),
+            _ if variation_depth > 0 => {}
+            c if c.is_whitespace() => finish_token(&mut token, &mut moves),
+            c => token.push(c),
+        }
+
+        if c == '{' || c == ';' || c == '(' {
+            finish_token(&mut token, &mut moves);
+        }
+    }
+    finish_token(&mut token, &mut moves);
+
+    moves
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use leben_chess::moves::{ChessMove, PieceMovement};
+    use crate::fen::square;
+
+    // the writer only looks at the SAN
+    fn some_move() -> ChessMove {
+        ChessMove { piece_movement: PieceMovement { from: square(4, 1), to: square(4, 3) }, promotion: None }
+    }
+
+    #[test]
+    fn reads_tags_and_moves() {
+
+        let file = PgnFile::parse(
+            "[Event \"Club \\\"final\\\"\"]\n[White \"Alice\"]\n[Black \"Bob\"]\n\n1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 1-0\n",
+        ).unwrap();
+
+        assert_eq!(file.tag("Event"), Some("Club \"final\""));
+        assert_eq!(file.tag("White"), Some("Alice"));
+        assert_eq!(file.tag("Round"), None);
+        assert_eq!(file.moves, ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6"]);
+    }
+
+    #[test]
+    fn skips_comments_variations_and_glyphs() {
+
+        let file = PgnFile::parse(
+            "1.e4 {best by test} e5 2. Nf3 (2. f4 exf4 (2... d5)) Nc6 $1 ; the main line\n3... a6?! 4. 0-0 *",
+        ).unwrap();
+
+        assert_eq!(file.moves, ["e4", "e5", "Nf3", "Nc6", "a6?!", "0-0"]);
+    }
+
+    #[test]
+    fn skips_move_numbers_in_any_form() {
+
+        let file = PgnFile::parse("1. e4 ... e5 2 . Nf3 2... Nc6 3 ...a6 4.Bb5 ...\n*").unwrap();
+
+        assert_eq!(file.moves, ["e4", "e5", "Nf3", "Nc6", "a6", "Bb5"]);
+    }
+
+    #[test]
+    fn reads_only_the_first_game() {
+
+        let file = PgnFile::parse("[Event \"1\"]\n\n1. d4 d5 *\n\n[Event \"2\"]\n\n1. e4 *\n").unwrap();
+
+        assert_eq!(file.tag("Event"), Some("1"));
+        assert_eq!(file.moves, ["d4", "d5"]);
+    }
+
+    #[test]
+    fn saves_in_the_same_second_dont_overwrite() {
+
+        let dir = std::env::temp_dir().join(format!("chess-gui-pgn-{}", std::process::id()));
+        let moves = [PlayedMove { mv: some_move(), san: "e4".to_string(), captured: None }];
+
+        let game = PgnGame {
+            event: "?".to_string(),
+            site: "?".to_string(),
+            white: "?".to_string(),
+            black: "?".to_string(),
+            outcome: Outcome::Ongoing,
+            termination: None,
+            start_fen: None,
+            start_position: PositionInfo::starting(),
+            moves: &moves,
+        };
+
+        let first = game.save_in(&dir).unwrap();
+        let second = game.save_in(&dir).unwrap();
+
+        assert_ne!(first, second);
+        assert!(first.exists() && second.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_tag() {
+        assert!(matches!(PgnFile::parse("[Event Casual]\n\n1. e4 *"), Err(PgnError::InvalidTag(_))));
+    }
+
+    #[test]
+    fn write_and_read_back() {
+
+        let moves: Vec<PlayedMove> = ["e4", "e5", "Qh5", "Nc6", "Bc4", "Nf6", "Qxf7#"]
+            .iter()
+            .map(|san| PlayedMove { mv: some_move(), san: san.to_string(), captured: None })
+            .collect();
+
+        let game = PgnGame {
+            event: "Casual game".to_string(),
+            site: "?".to_string(),
+            white: "?".to_string(),
+            black: "?".to_string(),
+            outcome: Outcome::Ongoing,
+            termination: None,
+            start_fen: None,
+            start_position: PositionInfo::starting(),
+            moves: &moves,
+        };
+
+        let pgn = game.write();
+        assert!(pgn.contains("\n\n1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# *\n"));
+
+        let file = PgnFile::parse(&pgn).unwrap();
+        assert_eq!(file.tag("Result"), Some("*"));
+        assert_eq!(file.moves, ["e4", "e5", "Qh5", "Nc6", "Bc4", "Nf6", "Qxf7#"]);
+    }
+
+    #[test]
+    fn timed_out_game_reads_back() {
+
+        let moves: Vec<PlayedMove> = ["d4", "d5"]
+            .iter()
+            .map(|san| PlayedMove { mv: some_move(), san: san.to_string(), captured: None })
+            .collect();
+
+        let game = PgnGame {
+            event: "Blitz \"final\"\nround 2\t".to_string(),
+            site: "?".to_string(),
+            white: "?".to_string(),
+            black: "?".to_string(),
+            outcome: Outcome::WhiteWon,
+            termination: Some(Termination::TimeForfeit),
+            start_fen: None,
+            start_position: PositionInfo::starting(),
+            moves: &moves,
+        };
+
+        let file = PgnFile::parse(&game.write()).unwrap();
+        assert_eq!(file.tag("Termination"), Some("time forfeit"));
+        assert_eq!(file.tag("Result"), Some("1-0"));
+        assert_eq!(file.tag("Event"), Some("Blitz \"final\" round 2 "));
+        assert_eq!(file.moves, ["d4", "d5"]);
+    }
+
+    #[test]
+    fn black_starts_numbering() {
+
+        let moves = [
+            PlayedMove { mv: some_move(), san: "e5".to_string(), captured: None },
+            PlayedMove { mv: some_move(), san: "Nf3".to_string(), captured: None },
+        ];
+        let mut start_position = PositionInfo::starting();
+        start_position.active_player = PlayerColor::Black;
+        start_position.fullmove_number = 12;
+
+        let game = PgnGame {
+            event: "?".to_string(),
+            site: "?".to_string(),
+            white: "?".to_string(),
+            black: "?".to_string(),
+            outcome: Outcome::Ongoing,
+            termination: None,
+            start_fen: None,
+            start_position,
+            moves: &moves,
+        };
+
+        assert!(game.write().ends_with("\n12... e5 13. Nf3 *\n"));
+    }
+
+}
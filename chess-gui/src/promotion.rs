@@ -0,0 +1,101 @@
+// promotion picker: a column of the four pieces, starting on the promotion square and
+// reaching into the board (Queen, Knight, Rook, Bishop, like the usual chess sites).
+//
+// choices() is the only place that knows where the pieces are, draw and choice_at both use it.
+// clicking anywhere else, or Escape, cancels the promotion.
+
+use ggez::graphics::{self, Canvas, Color, Rect};
+use ggez::{Context, GameResult};
+use ggez::glam::*;
+use leben_chess::board::board_pos::BoardPosition;
+use leben_chess::board::piece::{Piece, PieceType, PlayerColor};
+use leben_chess::moves::PromotionType;
+
+use crate::ChessBoard;
+use crate::atlas::PieceAtlas;
+
+
+const ORDER: [PromotionType; 4] = [PromotionType::Queen, PromotionType::Knight, PromotionType::Rook, PromotionType::Bishop];
+
+pub struct PromotionPicker {
+    color: PlayerColor, // the promoting side
+    top_left: Vec2, // of the promotion square, on the screen
+    board: Rect, // where the board is on the screen
+}
+
+pub fn piece_type(promotion: PromotionType) -> PieceType {
+
+    match promotion {
+        PromotionType::Knight => PieceType::Knight,
+        PromotionType::Bishop => PieceType::Bishop,
+        PromotionType::Rook => PieceType::Rook,
+        PromotionType::Queen => PieceType::Queen,
+    }
+}
+
+impl PromotionPicker {
+
+    pub fn new(to: BoardPosition, color: PlayerColor, board: &ChessBoard) -> Self {
+        PromotionPicker { color, top_left: board.calc_square_pos(to), board: board.area }
+    }
+
+    fn choices(&self) -> [(PromotionType, Rect); 4] {
+
+        let top_left = self.top_left;
+        let square_size = self.square_size();
+
+        // promotion squares are on the top or bottom edge, the column goes towards the middle
+        let step = if top_left.y < self.board.center().y { square_size } else { -square_size };
+
+        let mut choices = ORDER.map(|promotion| (promotion, Rect::default()));
+
+        for (index, (_, area)) in choices.iter_mut().enumerate() {
+            *area = Rect::new(top_left.x, top_left.y + index as f32*step, square_size, square_size);
+        }
+
+        choices
+    }
+
+    fn square_size(&self) -> f32 {
+        self.board.w / 8.0
+    }
+
+    pub fn choice_at(&self, point: Vec2) -> Option<PromotionType> {
+
+        self.choices()
+            .into_iter()
+            .find(|(_, area)| area.contains(point))
+            .map(|(promotion, _)| promotion)
+    }
+
+    pub fn draw(&self, ctx: &mut Context, canvas: &mut Canvas, atlas: &PieceAtlas) -> GameResult {
+
+        // dim the board, so the column stands out
+        let overlay = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(0.0, 0.0, self.board.w, self.board.h),
+            Color::from_rgba(0, 0, 0, 120),
+        )?;
+        canvas.draw(&overlay, Vec2::new(self.board.x, self.board.y));
+
+        for (promotion, area) in self.choices() {
+
+            let background = graphics::Mesh::new_circle(
+                ctx,
+                graphics::DrawMode::fill(),
+                area.center(),
+                self.square_size()*0.48,
+                0.5,
+                Color::from_rgb(235, 235, 235),
+            )?;
+            canvas.draw(&background, Vec2::new(0.0, 0.0));
+
+            let piece = Piece { piece_type: piece_type(promotion), player: self.color };
+            atlas.draw_piece(canvas, piece, Vec2::new(area.x, area.y), area.w);
+        }
+
+        Ok(())
+    }
+
+}
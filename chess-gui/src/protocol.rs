@@ -0,0 +1,720 @@
+// the 128-byte message protocol spoken with the opponent
+//
+// every message is exactly MSG_SIZE bytes: fields separated by ':', followed by '0'-padding
+//
+// ChessINIT:<color>:<fen>:<time control>:000...
+//   sent once by the host right after accepting the connection
+//   color: "W" or "B", the color the client plays
+//   fen: the position the game starts from, in FEN-notation
+//   time control: "-" for none, otherwise base time and increment/delay in milliseconds:
+//                 "S300000" sudden death, "F300000+2000" Fischer, "B300000+2000" Bronstein
+//
+// ChessMOVE:<move>:<status>:<fen>:<white time>:<black time>:000...
+//   move: 5 characters, eg. A1A50 (capital letters), the last character indicating
+//         promotion piece type ('K'night, 'B'ishop, 'R'ook, 'Q'ueen or '0' for none)
+//   status: "0-0" ongoing, "1-0" white won, "0-1" black won, "1-1" draw
+//   fen: the board after the move, in FEN-notation
+//   times: remaining clock times in milliseconds after the move, "-" in untimed games.
+//          the mover's own time is what counts, the receiver takes it over.
+//          both fields may be left out, as in the original ChessMOVE:<move>:<status>:<fen>:000...
+//
+// ChessTIMEOUT:<color>:000...
+//   the sender's own time ran out (color: the sender's color). Only the player whose clock
+//   it is may declare this, so both sides agree on who flagged
+//
+// ChessTAKEBACK:<ply>:000...
+//   asks the opponent to take moves back, ply: number of half-moves the game has left afterwards
+//   (an absolute count, so that a move crossing the request on the wire can't shift it)
+// ChessTAKEBACKOK:<ply>:000...
+//   the opponent agreed, both sides go back to <ply> half-moves
+// ChessTAKEBACKNO:000...
+//   the opponent declined
+//
+// ChessRESIGN:000...    the sender resigns
+// ChessDRAW:000...      the sender offers a draw
+// ChessDRAWOK:000...    the sender accepts the draw offer
+// ChessDRAWNO:000...    the sender declines the draw offer
+// ChessABORT:000...     the sender aborts the game, only allowed before both sides have moved
+
+use std::fmt;
+use std::time::Duration;
+
+use leben_chess::board::board_pos::BoardPosition;
+use leben_chess::board::piece::PlayerColor;
+use leben_chess::chess::GameStatus;
+use leben_chess::moves::{ChessMove, PieceMovement, PromotionType};
+
+use crate::clock::TimeControl;
+use crate::network::MSG_SIZE;
+
+
+const SEPARATOR: char = ':';
+const PADDING: char = '0';
+
+pub enum ProtocolMessage {
+    Init { client_color: PlayerColor, fen: String, time_control: Option<TimeControl> },
+    // clock: remaining (White, Black) time
+    Move { mv: ChessMove, status: Outcome, fen: String, clock: Option<(Duration, Duration)> },
+    Timeout { color: PlayerColor },
+    TakebackRequest { ply: usize },
+    TakebackAccept { ply: usize },
+    TakebackDecline,
+    Resign,
+    DrawOffer,
+    DrawAccept,
+    DrawDecline,
+    Abort,
+}
+
+// game state as reported by the player who made the move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Ongoing,
+    WhiteWon,
+    BlackWon,
+    Draw,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtocolError {
+    WrongLength(usize),
+    NotAscii,
+    UnknownMessage(String),
+    MissingField(&'static str),
+    InvalidColor(String),
+    InvalidMove(String),
+    InvalidStatus(String),
+    InvalidFen(String),
+    InvalidPly(String),
+    InvalidTimeControl(String),
+    InvalidClock(String),
+    InvalidPadding,
+    TooLong(usize), // an outgoing message that doesn't fit into MSG_SIZE bytes
+}
+
+impl fmt::Display for ProtocolError {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::WrongLength(len) => write!(f, "message is {} bytes, expected {}", len, MSG_SIZE),
+            ProtocolError::NotAscii => write!(f, "message contains non-ASCII bytes"),
+            ProtocolError::UnknownMessage(id) => write!(f, "unknown message identifier {:?}", id),
+            ProtocolError::MissingField(field) => write!(f, "message is missing the {} field", field),
+            ProtocolError::InvalidColor(color) => write!(f, "invalid color {:?}", color),
+            ProtocolError::InvalidMove(mv) => write!(f, "invalid move {:?}", mv),
+            ProtocolError::InvalidStatus(status) => write!(f, "invalid game status {:?}", status),
+            ProtocolError::InvalidFen(fen) => write!(f, "invalid FEN field {:?}", fen),
+            ProtocolError::InvalidPly(ply) => write!(f, "invalid ply count {:?}", ply),
+            ProtocolError::InvalidTimeControl(control) => write!(f, "invalid time control {:?}", control),
+            ProtocolError::InvalidClock(time) => write!(f, "invalid clock time {:?}", time),
+            ProtocolError::InvalidPadding => write!(f, "padding must consist of '0' only"),
+            ProtocolError::TooLong(len) => write!(f, "message is {} bytes, at most {} fit", len, MSG_SIZE),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+impl From<GameStatus> for Outcome {
+
+    fn from(status: GameStatus) -> Self {
+        match status {
+            GameStatus::NotYetStarted | GameStatus::Normal => Outcome::Ongoing,
+            GameStatus::Win(PlayerColor::White, _) => Outcome::WhiteWon,
+            GameStatus::Win(PlayerColor::Black, _) => Outcome::BlackWon,
+            GameStatus::Draw(_) => Outcome::Draw,
+        }
+    }
+}
+
+impl Outcome {
+
+    pub fn win(color: PlayerColor) -> Self {
+        match color {
+            PlayerColor::White => Outcome::WhiteWon,
+            PlayerColor::Black => Outcome::BlackWon,
+        }
+    }
+
+    fn token(&self) -> &'static str {
+        match self {
+            Outcome::Ongoing => "0-0",
+            Outcome::WhiteWon => "1-0",
+            Outcome::BlackWon => "0-1",
+            Outcome::Draw => "1-1",
+        }
+    }
+
+    fn from_token(token: &str) -> Result<Self, ProtocolError> {
+        match token {
+            "0-0" => Ok(Outcome::Ongoing),
+            "1-0" => Ok(Outcome::WhiteWon),
+            "0-1" => Ok(Outcome::BlackWon),
+            "1-1" => Ok(Outcome::Draw),
+            _ => Err(ProtocolError::InvalidStatus(token.to_string())),
+        }
+    }
+}
+
+impl ProtocolMessage {
+
+    pub fn encode(&self) -> Result<[u8; MSG_SIZE], ProtocolError> {
+
+        let fields = match self {
+            ProtocolMessage::Init { client_color, fen, time_control } => {
+                vec!["ChessINIT".to_string(), encode_color(*client_color).to_string(), fen.clone(), encode_time_control(*time_control)]
+            }
+            ProtocolMessage::Move { mv, status, fen, clock } => {
+                let (white_time, black_time) = match clock {
+                    Some((white, black)) => (white.as_millis().to_string(), black.as_millis().to_string()),
+                    None => ("-".to_string(), "-".to_string()),
+                };
+                vec!["ChessMOVE".to_string(), encode_move(*mv), status.token().to_string(), fen.clone(), white_time, black_time]
+            }
+            ProtocolMessage::Timeout { color } => vec!["ChessTIMEOUT".to_string(), encode_color(*color).to_string()],
+            ProtocolMessage::TakebackRequest { ply } => vec!["ChessTAKEBACK".to_string(), ply.to_string()],
+            ProtocolMessage::TakebackAccept { ply } => vec!["ChessTAKEBACKOK".to_string(), ply.to_string()],
+            ProtocolMessage::TakebackDecline => vec!["ChessTAKEBACKNO".to_string()],
+            ProtocolMessage::Resign => vec!["ChessRESIGN".to_string()],
+            ProtocolMessage::DrawOffer => vec!["ChessDRAW".to_string()],
+            ProtocolMessage::DrawAccept => vec!["ChessDRAWOK".to_string()],
+            ProtocolMessage::DrawDecline => vec!["ChessDRAWNO".to_string()],
+            ProtocolMessage::Abort => vec!["ChessABORT".to_string()],
+        };
+
+        let mut msg = String::new();
+        for field in fields {
+            msg += &field;
+            msg.push(SEPARATOR);
+        }
+
+        // a FEN is at most ~90 characters, long ones and huge clock times can still add up to more
+        if msg.len() > MSG_SIZE {
+            return Err(ProtocolError::TooLong(msg.len()));
+        }
+
+        let mut buf = [PADDING as u8; MSG_SIZE];
+        buf[..msg.len()].copy_from_slice(msg.as_bytes());
+
+        Ok(buf)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, ProtocolError> {
+
+        if bytes.len() != MSG_SIZE {
+            return Err(ProtocolError::WrongLength(bytes.len()));
+        }
+
+        if !bytes.is_ascii() {
+            return Err(ProtocolError::NotAscii);
+        }
+
+        let msg = std::str::from_utf8(bytes).map_err(|_| ProtocolError::NotAscii)?;
+        let mut fields = msg.split(SEPARATOR);
+
+        let id = fields.next().unwrap_or("");
+
+        let message = match id {
+
+            "ChessINIT" => {
+                let color = fields.next().ok_or(ProtocolError::MissingField("color"))?;
+                let fen = fields.next().ok_or(ProtocolError::MissingField("fen"))?;
+                let time_control = fields.next().ok_or(ProtocolError::MissingField("time control"))?;
+
+                ProtocolMessage::Init {
+                    client_color: decode_color(color)?,
+                    fen: decode_fen(fen)?,
+                    time_control: decode_time_control(time_control)?,
+                }
+            }
+
+            "ChessMOVE" => {
+                let mv = fields.next().ok_or(ProtocolError::MissingField("move"))?;
+                let status = fields.next().ok_or(ProtocolError::MissingField("status"))?;
+                let fen = fields.next().ok_or(ProtocolError::MissingField("fen"))?;
+
+                // without the clock fields only the padding is left
+                let clock = if fields.clone().count() > 1 {
+
+                    let white_time = fields.next().ok_or(ProtocolError::MissingField("white time"))?;
+                    let black_time = fields.next().ok_or(ProtocolError::MissingField("black time"))?;
+
+                    match (white_time, black_time) {
+                        ("-", "-") => None,
+                        _ => Some((decode_millis(white_time, ProtocolError::InvalidClock)?, decode_millis(black_time, ProtocolError::InvalidClock)?)),
+                    }
+                } else {
+                    None
+                };
+
+                ProtocolMessage::Move {
+                    mv: decode_move(mv)?,
+                    status: Outcome::from_token(status)?,
+                    fen: decode_fen(fen)?,
+                    clock,
+                }
+            }
+
+            "ChessTIMEOUT" => {
+                let color = fields.next().ok_or(ProtocolError::MissingField("color"))?;
+                ProtocolMessage::Timeout { color: decode_color(color)? }
+            }
+
+            "ChessTAKEBACK" => {
+                let ply = fields.next().ok_or(ProtocolError::MissingField("ply"))?;
+                ProtocolMessage::TakebackRequest { ply: decode_ply(ply)? }
+            }
+
+            "ChessTAKEBACKOK" => {
+                let ply = fields.next().ok_or(ProtocolError::MissingField("ply"))?;
+                ProtocolMessage::TakebackAccept { ply: decode_ply(ply)? }
+            }
+
+            "ChessTAKEBACKNO" => ProtocolMessage::TakebackDecline,
+            "ChessRESIGN" => ProtocolMessage::Resign,
+            "ChessDRAW" => ProtocolMessage::DrawOffer,
+            "ChessDRAWOK" => ProtocolMessage::DrawAccept,
+            "ChessDRAWNO" => ProtocolMessage::DrawDecline,
+            "ChessABORT" => ProtocolMessage::Abort,
+
+            _ => return Err(ProtocolError::UnknownMessage(id.to_string())),
+        };
+
+        // whatever follows the last field is padding (and may not contain another ':')
+        let padding = fields.next().ok_or(ProtocolError::InvalidPadding)?;
+
+        if fields.next().is_some() || !padding.chars().all(|c| c == PADDING) {
+            return Err(ProtocolError::InvalidPadding);
+        }
+
+        Ok(message)
+    }
+
+}
+
+fn decode_ply(ply: &str) -> Result<usize, ProtocolError> {
+
+    // plain decimal digits, "+1" or " 1" would be accepted by parse()
+    if ply.is_empty() || !ply.chars().all(|c| c.is_ascii_digit()) {
+        return Err(ProtocolError::InvalidPly(ply.to_string()));
+    }
+
+    ply.parse().map_err(|_| ProtocolError::InvalidPly(ply.to_string()))
+}
+
+// plain decimal digits only, like decode_ply
+fn decode_millis(millis: &str, error: impl Fn(String) -> ProtocolError) -> Result<Duration, ProtocolError> {
+
+    if millis.is_empty() || !millis.chars().all(|c| c.is_ascii_digit()) {
+        return Err(error(millis.to_string()));
+    }
+
+    millis.parse().map(Duration::from_millis).map_err(|_| error(millis.to_string()))
+}
+
+fn encode_time_control(time_control: Option<TimeControl>) -> String {
+
+    match time_control {
+        None => "-".to_string(),
+        Some(TimeControl::SuddenDeath { base }) => format!("S{}", base.as_millis()),
+        Some(TimeControl::Fischer { base, increment }) => format!("F{}+{}", base.as_millis(), increment.as_millis()),
+        Some(TimeControl::Bronstein { base, delay }) => format!("B{}+{}", base.as_millis(), delay.as_millis()),
+    }
+}
+
+fn decode_time_control(time_control: &str) -> Result<Option<TimeControl>, ProtocolError> {
+
+    let invalid = |_: String| ProtocolError::InvalidTimeControl(time_control.to_string());
+
+    if time_control == "-" {
+        return Ok(None);
+    }
+
+    let (kind, times) = time_control.split_at(time_control.chars().next().map_or(0, char::len_utf8));
+
+    let control = match (kind, times.split_once('+')) {
+        ("S", None) => TimeControl::SuddenDeath { base: decode_millis(times, invalid)? },
+        ("F", Some((base, increment))) => TimeControl::Fischer {
+            base: decode_millis(base, invalid)?,
+            increment: decode_millis(increment, invalid)?,
+        },
+        ("B", Some((base, delay))) => TimeControl::Bronstein {
+            base: decode_millis(base, invalid)?,
+            delay: decode_millis(delay, invalid)?,
+        },
+        _ => return Err(ProtocolError::InvalidTimeControl(time_control.to_string())),
+    };
+
+    Ok(Some(control))
+}
+
+fn encode_color(color: PlayerColor) -> &'static str {
+    match color {
+        PlayerColor::White => "W",
+        PlayerColor::Black => "B",
+    }
+}
+
+fn decode_color(color: &str) -> Result<PlayerColor, ProtocolError> {
+    match color {
+        "W" => Ok(PlayerColor::White),
+        "B" => Ok(PlayerColor::Black),
+        _ => Err(ProtocolError::InvalidColor(color.to_string())),
+    }
+}
+
+fn decode_fen(fen: &str) -> Result<String, ProtocolError> {
+
+    // only checks the characters, the position itself is validated by the fen module
+    let fen_chars = |c: char| c.is_ascii_alphanumeric() || c == '/' || c == '-' || c == ' ';
+
+    if fen.is_empty() || fen.trim() != fen || !fen.chars().all(fen_chars) {
+        return Err(ProtocolError::InvalidFen(fen.to_string()));
+    }
+
+    Ok(fen.to_string())
+}
+
+fn encode_move(mv: ChessMove) -> String {
+
+    let files = ["A", "B", "C", "D", "E", "F", "G", "H"];
+    let ranks = ["1", "2", "3", "4", "5", "6", "7", "8"];
+
+    let move_string: String = format!(
+        "{}{}{}{}{}",
+        files[mv.piece_movement.from.file.get() as usize],
+        ranks[mv.piece_movement.from.rank.get() as usize],
+        files[mv.piece_movement.to.file.get() as usize],
+        ranks[mv.piece_movement.to.rank.get() as usize],
+        match mv.promotion {
+            Some(PromotionType::Knight) => "K",
+            Some(PromotionType::Bishop) => "B",
+            Some(PromotionType::Rook) => "R",
+            Some(PromotionType::Queen) => "Q",
+            None => "0",
+        }
+    );
+
+    move_string
+}
+
+fn decode_move(chess_move: &str) -> Result<ChessMove, ProtocolError> {
+
+    let invalid = || ProtocolError::InvalidMove(chess_move.to_string());
+
+    let chars: Vec<char> = chess_move.chars().collect();
+
+    if chars.len() != 5 {
+        return Err(invalid());
+    }
+
+    let square = |file: char, rank: char| -> Result<BoardPosition, ProtocolError> {
+
+        if !('A'..='H').contains(&file) || !('1'..='8').contains(&rank) {
+            return Err(invalid());
+        }
+        BoardPosition::try_from((file as u8 - b'A', rank as u8 - b'1')).map_err(|_| invalid())
+    };
+
+    let from = square(chars[0], chars[1])?;
+    let to = square(chars[2], chars[3])?;
+
+    let promotion = match chars[4] {
+        'K' => Some(PromotionType::Knight),
+        'B' => Some(PromotionType::Bishop),
+        'R' => Some(PromotionType::Rook),
+        'Q' => Some(PromotionType::Queen),
+        '0' => None,
+        _ => return Err(invalid()),
+    };
+
+    Ok(ChessMove{piece_movement: PieceMovement{from, to}, promotion})
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR";
+
+    fn all_promotions() -> [Option<PromotionType>; 5] {
+        [None, Some(PromotionType::Knight), Some(PromotionType::Bishop), Some(PromotionType::Rook), Some(PromotionType::Queen)]
+    }
+
+    fn move_message(mv: ChessMove, status: Outcome, fen: &str) -> ProtocolMessage {
+        ProtocolMessage::Move { mv, status, fen: fen.to_string(), clock: None }
+    }
+
+    fn frame(msg: &str) -> Vec<u8> {
+        let mut bytes = msg.as_bytes().to_vec();
+        bytes.resize(MSG_SIZE, b'0');
+        bytes
+    }
+
+    #[test]
+    fn every_move_round_trips() {
+
+        for from in 0..64u8 {
+            for to in 0..64u8 {
+                for promotion in all_promotions() {
+
+                    let mv = ChessMove {
+                        piece_movement: PieceMovement {
+                            from: BoardPosition::try_from((from % 8, from / 8)).unwrap(),
+                            to: BoardPosition::try_from((to % 8, to / 8)).unwrap(),
+                        },
+                        promotion,
+                    };
+
+                    let encoded = move_message(mv, Outcome::Ongoing, START_FEN).encode().unwrap();
+                    assert_eq!(encoded.len(), MSG_SIZE);
+
+                    match ProtocolMessage::decode(&encoded).unwrap() {
+                        ProtocolMessage::Move { mv: decoded, .. } => {
+                            assert!(decoded.piece_movement.from == mv.piece_movement.from);
+                            assert!(decoded.piece_movement.to == mv.piece_movement.to);
+                            assert_eq!(encode_move(decoded), encode_move(mv));
+                        }
+                        _ => panic!("decoded into the wrong message type"),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn statuses_fens_and_colors_round_trip() {
+
+        let mv = decode_move("E2E40").unwrap();
+        let fens = [START_FEN, "8/8/8/8/8/8/8/8", "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"];
+
+        for status in [Outcome::Ongoing, Outcome::WhiteWon, Outcome::BlackWon, Outcome::Draw] {
+            for fen in fens {
+                match ProtocolMessage::decode(&move_message(mv, status, fen).encode().unwrap()).unwrap() {
+                    ProtocolMessage::Move { status: decoded_status, fen: decoded_fen, .. } => {
+                        assert_eq!(decoded_status, status);
+                        assert_eq!(decoded_fen, fen);
+                    }
+                    _ => panic!("decoded into the wrong message type"),
+                }
+            }
+        }
+
+        for color in [PlayerColor::White, PlayerColor::Black] {
+            for fen in fens {
+                match ProtocolMessage::decode(&ProtocolMessage::Init { client_color: color, fen: fen.to_string(), time_control: None }.encode().unwrap()).unwrap() {
+                    ProtocolMessage::Init { client_color, fen: decoded_fen, .. } => {
+                        assert!(client_color == color);
+                        assert_eq!(decoded_fen, fen);
+                    }
+                    _ => panic!("decoded into the wrong message type"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn clocks_round_trip() {
+
+        let mv = decode_move("E2E40").unwrap();
+        let ms = Duration::from_millis;
+
+        // the longest realistic FEN, and 27 hours on both clocks
+        let fen = "rnbqkbnr/pppppppp/1N1N1N1N/n1n1n1n1/1N1N1N1N/n1n1n1n1/PPPPPPPP/RNBQKBNR w KQkq - 100 200";
+        let clocks = [None, Some((ms(0), ms(0))), Some((ms(300000), ms(1))), Some((ms(99999999), ms(99999999)))];
+
+        for clock in clocks {
+
+            let msg = ProtocolMessage::Move { mv, status: Outcome::Ongoing, fen: fen.to_string(), clock };
+
+            match ProtocolMessage::decode(&msg.encode().unwrap()).unwrap() {
+                ProtocolMessage::Move { clock: decoded, .. } => assert_eq!(decoded, clock),
+                _ => panic!("decoded into the wrong message type"),
+            }
+        }
+
+        let time_controls = [
+            None,
+            Some(TimeControl::SuddenDeath { base: ms(300000) }),
+            Some(TimeControl::Fischer { base: ms(180000), increment: ms(2000) }),
+            Some(TimeControl::Bronstein { base: ms(600000), delay: ms(0) }),
+        ];
+
+        for time_control in time_controls {
+
+            let msg = ProtocolMessage::Init { client_color: PlayerColor::White, fen: START_FEN.to_string(), time_control };
+
+            match ProtocolMessage::decode(&msg.encode().unwrap()).unwrap() {
+                ProtocolMessage::Init { time_control: decoded, .. } => assert_eq!(decoded, time_control),
+                _ => panic!("decoded into the wrong message type"),
+            }
+        }
+
+        for color in [PlayerColor::White, PlayerColor::Black] {
+            match ProtocolMessage::decode(&ProtocolMessage::Timeout { color }.encode().unwrap()).unwrap() {
+                ProtocolMessage::Timeout { color: decoded } => assert!(decoded == color),
+                _ => panic!("decoded into the wrong message type"),
+            }
+        }
+    }
+
+    #[test]
+    fn takebacks_round_trip() {
+
+        for ply in [0, 1, 2, 57, 600] {
+
+            match ProtocolMessage::decode(&ProtocolMessage::TakebackRequest { ply }.encode().unwrap()).unwrap() {
+                ProtocolMessage::TakebackRequest { ply: decoded } => assert_eq!(decoded, ply),
+                _ => panic!("decoded into the wrong message type"),
+            }
+
+            match ProtocolMessage::decode(&ProtocolMessage::TakebackAccept { ply }.encode().unwrap()).unwrap() {
+                ProtocolMessage::TakebackAccept { ply: decoded } => assert_eq!(decoded, ply),
+                _ => panic!("decoded into the wrong message type"),
+            }
+        }
+
+        assert!(matches!(
+            ProtocolMessage::decode(&ProtocolMessage::TakebackDecline.encode().unwrap()).unwrap(),
+            ProtocolMessage::TakebackDecline
+        ));
+    }
+
+    #[test]
+    fn game_endings_round_trip() {
+
+        assert!(matches!(ProtocolMessage::decode(&ProtocolMessage::Resign.encode().unwrap()).unwrap(), ProtocolMessage::Resign));
+        assert!(matches!(ProtocolMessage::decode(&ProtocolMessage::DrawOffer.encode().unwrap()).unwrap(), ProtocolMessage::DrawOffer));
+        assert!(matches!(ProtocolMessage::decode(&ProtocolMessage::DrawAccept.encode().unwrap()).unwrap(), ProtocolMessage::DrawAccept));
+        assert!(matches!(ProtocolMessage::decode(&ProtocolMessage::DrawDecline.encode().unwrap()).unwrap(), ProtocolMessage::DrawDecline));
+        assert!(matches!(ProtocolMessage::decode(&ProtocolMessage::Abort.encode().unwrap()).unwrap(), ProtocolMessage::Abort));
+    }
+
+    #[test]
+    fn matches_the_documented_layout() {
+
+        let encoded = move_message(decode_move("A7A8Q").unwrap(), Outcome::WhiteWon, START_FEN).encode().unwrap();
+        assert_eq!(encoded.to_vec(), frame(&format!("ChessMOVE:A7A8Q:1-0:{}:-:-:", START_FEN)));
+
+        let timed = ProtocolMessage::Move {
+            mv: decode_move("A7A8Q").unwrap(),
+            status: Outcome::Ongoing,
+            fen: START_FEN.to_string(),
+            clock: Some((Duration::from_millis(61500), Duration::from_millis(2000))),
+        };
+        assert_eq!(timed.encode().unwrap().to_vec(), frame(&format!("ChessMOVE:A7A8Q:0-0:{}:61500:2000:", START_FEN)));
+
+        let init = ProtocolMessage::Init {
+            client_color: PlayerColor::Black,
+            fen: START_FEN.to_string(),
+            time_control: Some(TimeControl::Fischer { base: Duration::from_secs(300), increment: Duration::from_secs(3) }),
+        };
+        assert_eq!(init.encode().unwrap().to_vec(), frame(&format!("ChessINIT:B:{}:F300000+3000:", START_FEN)));
+        assert_eq!(ProtocolMessage::Timeout { color: PlayerColor::White }.encode().unwrap().to_vec(), frame("ChessTIMEOUT:W:"));
+
+        assert_eq!(ProtocolMessage::TakebackRequest { ply: 12 }.encode().unwrap().to_vec(), frame("ChessTAKEBACK:12:"));
+        assert_eq!(ProtocolMessage::TakebackAccept { ply: 12 }.encode().unwrap().to_vec(), frame("ChessTAKEBACKOK:12:"));
+        assert_eq!(ProtocolMessage::TakebackDecline.encode().unwrap().to_vec(), frame("ChessTAKEBACKNO:"));
+        assert_eq!(ProtocolMessage::Resign.encode().unwrap().to_vec(), frame("ChessRESIGN:"));
+        assert_eq!(ProtocolMessage::DrawOffer.encode().unwrap().to_vec(), frame("ChessDRAW:"));
+        assert_eq!(ProtocolMessage::DrawAccept.encode().unwrap().to_vec(), frame("ChessDRAWOK:"));
+        assert_eq!(ProtocolMessage::DrawDecline.encode().unwrap().to_vec(), frame("ChessDRAWNO:"));
+        assert_eq!(ProtocolMessage::Abort.encode().unwrap().to_vec(), frame("ChessABORT:"));
+    }
+
+    #[test]
+    fn decodes_moves_without_clock_fields() {
+
+        match ProtocolMessage::decode(&frame(&format!("ChessMOVE:E2E40:0-0:{}:", START_FEN))).unwrap() {
+            ProtocolMessage::Move { mv, status, fen, clock } => {
+                assert_eq!(encode_move(mv), "E2E40");
+                assert_eq!(status, Outcome::Ongoing);
+                assert_eq!(fen, START_FEN);
+                assert_eq!(clock, None);
+            }
+            _ => panic!("decoded into the wrong message type"),
+        }
+
+        // but not just one of them
+        assert!(ProtocolMessage::decode(&frame(&format!("ChessMOVE:E2E40:0-0:{}:1000:", START_FEN))).is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_length() {
+
+        let short = &frame("ChessMOVE:E2E40:0-0:8/8/8/8/8/8/8/8:-:-:")[..MSG_SIZE - 1];
+        assert_eq!(ProtocolMessage::decode(short).err(), Some(ProtocolError::WrongLength(MSG_SIZE - 1)));
+
+        let mut long = frame("ChessMOVE:E2E40:0-0:8/8/8/8/8/8/8/8:-:-:");
+        long.push(b'0');
+        assert_eq!(ProtocolMessage::decode(&long).err(), Some(ProtocolError::WrongLength(MSG_SIZE + 1)));
+    }
+
+    #[test]
+    fn rejects_bad_padding() {
+
+        let mut msg = frame("ChessMOVE:E2E40:0-0:8/8/8/8/8/8/8/8:-:-:");
+        msg[MSG_SIZE - 1] = b' ';
+        assert_eq!(ProtocolMessage::decode(&msg).err(), Some(ProtocolError::InvalidPadding));
+
+        let extra_field = frame("ChessMOVE:E2E40:0-0:8/8/8/8/8/8/8/8:-:-:junk:");
+        assert_eq!(ProtocolMessage::decode(&extra_field).err(), Some(ProtocolError::InvalidPadding));
+    }
+
+    #[test]
+    fn rejects_bad_fields() {
+
+        let cases = [
+            ("ChessMOVX:E2E40:0-0:8/8/8/8/8/8/8/8:-:-:", ProtocolError::UnknownMessage("ChessMOVX".to_string())),
+            ("ChessMOVE:E2E4:0-0:8/8/8/8/8/8/8/8:-:-:", ProtocolError::InvalidMove("E2E4".to_string())),
+            ("ChessMOVE:e2e40:0-0:8/8/8/8/8/8/8/8:-:-:", ProtocolError::InvalidMove("e2e40".to_string())),
+            ("ChessMOVE:E9E40:0-0:8/8/8/8/8/8/8/8:-:-:", ProtocolError::InvalidMove("E9E40".to_string())),
+            ("ChessMOVE:I2E40:0-0:8/8/8/8/8/8/8/8:-:-:", ProtocolError::InvalidMove("I2E40".to_string())),
+            ("ChessMOVE:E7E8N:0-0:8/8/8/8/8/8/8/8:-:-:", ProtocolError::InvalidMove("E7E8N".to_string())),
+            ("ChessMOVE:E2E40:2-0:8/8/8/8/8/8/8/8:-:-:", ProtocolError::InvalidStatus("2-0".to_string())),
+            ("ChessMOVE:E2E40:0-0::-:-:", ProtocolError::InvalidFen("".to_string())),
+            ("ChessMOVE:E2E40:0-0:8/8/8/8/8/8/8/8 :-:-:", ProtocolError::InvalidFen("8/8/8/8/8/8/8/8 ".to_string())),
+            ("ChessINIT:X:8/8/8/8/8/8/8/8:-:", ProtocolError::InvalidColor("X".to_string())),
+            ("ChessINIT:W::", ProtocolError::InvalidFen("".to_string())),
+            ("ChessMOVE:E2E40:0-0:8/8/8/8/8/8/8/8:-:1000:", ProtocolError::InvalidClock("-".to_string())),
+            ("ChessMOVE:E2E40:0-0:8/8/8/8/8/8/8/8:1.5:1000:", ProtocolError::InvalidClock("1.5".to_string())),
+            ("ChessINIT:W:8/8/8/8/8/8/8/8:F300000:", ProtocolError::InvalidTimeControl("F300000".to_string())),
+            ("ChessINIT:W:8/8/8/8/8/8/8/8:S:", ProtocolError::InvalidTimeControl("S".to_string())),
+            ("ChessINIT:W:8/8/8/8/8/8/8/8:X1+1:", ProtocolError::InvalidTimeControl("X1+1".to_string())),
+            ("ChessTIMEOUT:-:", ProtocolError::InvalidColor("-".to_string())),
+            ("ChessTAKEBACK::", ProtocolError::InvalidPly("".to_string())),
+            ("ChessTAKEBACK:+1:", ProtocolError::InvalidPly("+1".to_string())),
+            ("ChessTAKEBACKOK:two:", ProtocolError::InvalidPly("two".to_string())),
+            ("ChessTAKEBACKNO:1:", ProtocolError::InvalidPadding),
+            ("ChessRESIGN:W:", ProtocolError::InvalidPadding),
+        ];
+
+        for (msg, error) in cases {
+            assert_eq!(ProtocolMessage::decode(&frame(msg)).err(), Some(error), "{}", msg);
+        }
+
+        let mut not_ascii = frame("ChessMOVE:E2E40:0-0:8/8/8/8/8/8/8/8:-:-:");
+        not_ascii[40] = 0xC3;
+        assert_eq!(ProtocolMessage::decode(&not_ascii).err(), Some(ProtocolError::NotAscii));
+    }
+
+    #[test]
+    fn too_long_messages_are_an_error() {
+
+        let long_fen = format!("{} w KQkq - 0 {}", START_FEN, "9".repeat(60));
+        let init = ProtocolMessage::Init { client_color: PlayerColor::White, fen: long_fen, time_control: None };
+        assert!(matches!(init.encode(), Err(ProtocolError::TooLong(_))));
+
+        let months = Duration::from_secs(60*60*24*365);
+        let timed = ProtocolMessage::Move {
+            mv: decode_move("E2E40").unwrap(),
+            status: Outcome::Ongoing,
+            fen: format!("{} b KQkq e3 0 1", "p1p1p1p1/".repeat(7) + "p1p1p1p1"),
+            clock: Some((months, months)),
+        };
+        assert!(matches!(timed.encode(), Err(ProtocolError::TooLong(_))));
+    }
+
+}
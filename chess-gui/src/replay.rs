@@ -0,0 +1,211 @@
+// read-only replay of a game loaded from a PGN file
+//
+// every move is checked with ChessGame::do_move while loading, the replay stops
+// at the first illegal move and reports it with its move number.
+// Left/Right step through the moves, Home/End jump to the start/end, Esc leaves the replay.
+
+use std::path::Path;
+
+use ggez::graphics::{self, Canvas, Color, DrawParam};
+use ggez::{Context, GameResult};
+use ggez::glam::*;
+use leben_chess::chess::ChessGame;
+use leben_chess::moves::ChessMove;
+
+use crate::fen::PositionInfo;
+use crate::pgn::{self, PgnError, PgnFile};
+use crate::san;
+use crate::{GameState, WIDTH, SQUARE_SIZE};
+
+
+pub struct Replay {
+    title: String,
+    start_fen: Option<String>,
+    moves: Vec<ChessMove>,
+    labels: Vec<String>, // "12... Nf6"
+    current: usize, // number of moves played on the shown board
+    pub game: ChessGame,
+    pub error: Option<PgnError>,
+}
+
+impl Replay {
+
+    pub fn load(path: &Path) -> Result<Self, PgnError> {
+        Replay::from_pgn(&PgnFile::load(path)?)
+    }
+
+    pub fn from_pgn(file: &PgnFile) -> Result<Self, PgnError> {
+
+        let start_fen = file.tag("FEN").map(str::to_string);
+        let (mut game, mut position) = Replay::starting_position(&start_fen)?;
+
+        let mut moves = Vec::new();
+        let mut labels = Vec::new();
+        let mut error = None;
+
+        for san in &file.moves {
+
+            let number = pgn::move_number(position.fullmove_number, position.active_player);
+
+            let played = san::parse(&game, san)
+                .map_err(|e| e.to_string())
+                .and_then(|mv| {
+                    let next_position = position.after_move(game.board(), mv);
+                    game.do_move(mv).map_err(|e| e.to_string())?;
+                    Ok((mv, next_position))
+                });
+
+            match played {
+                Ok((mv, next_position)) => {
+                    moves.push(mv);
+                    labels.push(format!("{} {}", number, san));
+                    position = next_position;
+                }
+                Err(reason) => {
+                    error = Some(PgnError::IllegalMove { number, san: san.clone(), reason });
+                    break;
+                }
+            }
+        }
+
+        let title = format!(
+            "{} vs {}",
+            file.tag("White").unwrap_or("?"),
+            file.tag("Black").unwrap_or("?"),
+        );
+
+        let mut replay = Replay { title, start_fen, moves, labels, current: 0, game, error };
+        replay.go_to(0);
+
+        Ok(replay)
+    }
+
+    fn starting_position(start_fen: &Option<String>) -> Result<(ChessGame, PositionInfo), PgnError> {
+        GameState::starting_position(start_fen).map_err(PgnError::InvalidFen)
+    }
+
+    // show the board after the first `ply` moves
+    pub fn go_to(&mut self, ply: usize) {
+
+        self.current = ply.min(self.moves.len());
+
+        // the moves were checked while loading, so replaying them can't fail
+        (self.game, _) = Replay::starting_position(&self.start_fen).unwrap();
+
+        for &mv in &self.moves[..self.current] {
+            let _ = self.game.do_move(mv);
+        }
+    }
+
+    pub fn forward(&mut self) {
+        self.go_to(self.current + 1);
+    }
+
+    pub fn back(&mut self) {
+        self.go_to(self.current.saturating_sub(1));
+    }
+
+    pub fn first(&mut self) {
+        self.go_to(0);
+    }
+
+    pub fn last(&mut self) {
+        self.go_to(self.moves.len());
+    }
+
+    pub fn draw(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+
+        let overlay = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(0.0, 0.0, WIDTH, SQUARE_SIZE),
+            Color::from_rgba(0, 0, 60, 200),
+        )?;
+        canvas.draw(&overlay, Vec2::new(0.0, 0.0));
+
+        let position = match self.current {
+            0 => "Start position".to_string(),
+            n => self.labels[n - 1].clone(),
+        };
+
+        let mut title = graphics::Text::new(format!(
+            "Replay: {}   {} ({}/{})",
+            self.title, position, self.current, self.moves.len(),
+        ));
+        title.set_scale(36.0);
+        canvas.draw(&title, DrawParam::default().dest([24.0, 12.0]));
+
+        let (hint, color) = match &self.error {
+            Some(e) => (format!("Stopped at {}", e), Color::from_rgb(255, 100, 100)),
+            None => ("Left/Right to step, Home/End to jump, Esc to leave".to_string(), Color::WHITE),
+        };
+
+        let mut hint_text = graphics::Text::new(hint);
+        hint_text.set_scale(28.0);
+        hint_text.set_bounds(Vec2::new(WIDTH - 48.0, SQUARE_SIZE/2.0));
+        canvas.draw(&hint_text, DrawParam::default().dest([24.0, SQUARE_SIZE/2.0 + 6.0]).color(color));
+
+        Ok(())
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::fen;
+    use crate::san::SanError;
+
+    fn replay(pgn: &str) -> Replay {
+        Replay::from_pgn(&PgnFile::parse(pgn).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn replays_a_whole_game() {
+
+        let mut replay = replay(
+            "[Event \"Casual game\"]\n[White \"Alice\"]\n[Black \"Bob\"]\n\n\
+             1. e4 e5 2. Nf3 Nc6 3. Bb5 {the Ruy Lopez} a6 (3... Nf6 4. O-O) 4. Ba4 Nf6 5. O-O $1 Be7 *\n",
+        );
+
+        assert!(replay.error.is_none());
+        assert_eq!(replay.title, "Alice vs Bob");
+        assert_eq!(replay.moves.len(), 10);
+        assert_eq!(replay.labels[5], "3... a6");
+        assert_eq!(replay.labels[8], "5. O-O");
+
+        replay.last();
+        assert_eq!(fen::placement(replay.game.board()), "r1bqk2r/1pppbppp/p1n2n2/4p3/B3P3/5N2/PPPP1PPP/RNBQ1RK1");
+
+        replay.back();
+        assert_eq!(fen::placement(replay.game.board()), "r1bqkb1r/1ppp1ppp/p1n2n2/4p3/B3P3/5N2/PPPP1PPP/RNBQ1RK1");
+    }
+
+    #[test]
+    fn stops_at_an_illegal_move() {
+
+        let replay = replay("1. e4 e5 2. Nf3 Ke6 3. Bc4 *");
+
+        assert_eq!(replay.moves.len(), 3);
+        assert!(matches!(
+            &replay.error,
+            Some(PgnError::IllegalMove { number, san, .. }) if number == "2..." && san == "Ke6"
+        ));
+    }
+
+    #[test]
+    fn stops_at_an_ambiguous_move() {
+
+        // the rook on a3 can go to h3 as well
+        let replay = replay("1. a4 a5 2. h4 h5 3. Ra3 Ra6 4. Rh3 Rh6 *");
+
+        assert_eq!(replay.moves.len(), 6);
+        assert!(matches!(
+            &replay.error,
+            Some(PgnError::IllegalMove { number, san, reason }) if number == "4." && san == "Rh3" && *reason == SanError::Ambiguous.to_string()
+        ));
+    }
+
+}
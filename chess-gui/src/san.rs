@@ -0,0 +1,462 @@
+// standard algebraic notation (SAN) for moves, e.g. "e4", "Nbd7", "exd5", "O-O", "e8=Q+"
+
+use std::fmt;
+
+use leben_chess::board::Board;
+use leben_chess::board::board_pos::BoardPosition;
+use leben_chess::board::piece::{Piece, PieceType, PlayerColor};
+use leben_chess::chess::{ChessGame, GameStatus};
+use leben_chess::moves::{ChessMove, PieceMovement, PromotionType};
+
+use crate::fen::{parse_square, square, square_name};
+use crate::opponent_color;
+
+
+// a move as it was played, kept in GameState's move list
+#[derive(Clone)]
+pub struct PlayedMove {
+    pub mv: ChessMove,
+    pub san: String,
+    pub captured: Option<Piece>, // see material.rs
+}
+
+fn file_char(file: u8) -> char {
+    (b'a' + file) as char
+}
+
+fn rank_char(rank: u8) -> char {
+    (b'1' + rank) as char
+}
+
+pub fn piece_letter(piece_type: PieceType) -> &'static str {
+    match piece_type {
+        PieceType::Pawn => "",
+        PieceType::Knight => "N",
+        PieceType::Bishop => "B",
+        PieceType::Rook => "R",
+        PieceType::Queen => "Q",
+        PieceType::King => "K",
+    }
+}
+
+pub fn promotion_letter(promotion: PromotionType) -> &'static str {
+    match promotion {
+        PromotionType::Knight => "N",
+        PromotionType::Bishop => "B",
+        PromotionType::Rook => "R",
+        PromotionType::Queen => "Q",
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SanError {
+    Malformed,
+    NoMatch,
+    Ambiguous,
+}
+
+impl fmt::Display for SanError {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SanError::Malformed => write!(f, "not a move in SAN"),
+            SanError::NoMatch => write!(f, "no piece can make this move"),
+            SanError::Ambiguous => write!(f, "more than one piece can make this move"),
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+fn all_squares() -> impl Iterator<Item = BoardPosition> {
+    (0..64u8).map(|i| square(i % 8, i / 8))
+}
+
+// SAN of mv without the check suffix, game is the game *before* the move
+pub fn describe(game: &ChessGame, mv: ChessMove) -> String {
+
+    let board = game.board();
+    let from = mv.piece_movement.from;
+    let to = mv.piece_movement.to;
+
+    let Some(piece) = board.get_piece(from) else {
+        return format!("{}{}", square_name(from), square_name(to)); // not a legal move anyway
+    };
+
+    let file_distance = from.file.get().abs_diff(to.file.get());
+
+    if piece.piece_type == PieceType::King && file_distance == 2 {
+        return if to.file.get() > from.file.get() { "O-O".to_string() } else { "O-O-O".to_string() };
+    }
+
+    let mut san = String::new();
+
+    if piece.piece_type == PieceType::Pawn {
+
+        // pawns only leave their file when capturing (possibly en passant)
+        if file_distance != 0 {
+            san.push(file_char(from.file.get()));
+            san.push('x');
+        }
+        san += &square_name(to);
+
+        if let Some(promotion) = mv.promotion {
+            san += "=";
+            san += promotion_letter(promotion);
+        }
+
+        return san;
+    }
+
+    san += piece_letter(piece.piece_type);
+
+    // other pieces of the same kind that could also move to the target square
+    let rivals: Vec<BoardPosition> = all_squares()
+        .filter(|&pos| pos != from && board.get_piece(pos) == Some(piece))
+        .filter(|&pos| game.available_moves(pos).get(to))
+        .collect();
+
+    if !rivals.is_empty() {
+
+        let same_file = rivals.iter().any(|pos| pos.file.get() == from.file.get());
+        let same_rank = rivals.iter().any(|pos| pos.rank.get() == from.rank.get());
+
+        if !same_file {
+            san.push(file_char(from.file.get()));
+        } else if !same_rank {
+            san.push(rank_char(from.rank.get()));
+        } else {
+            san += &square_name(from);
+        }
+    }
+
+    if board.get_piece(to).is_some() {
+        san.push('x');
+    }
+
+    san += &square_name(to);
+
+    san
+}
+
+// the move described by san, for the side to move in game
+// only finds the candidate, ChessGame::do_move still decides whether it's legal
+pub fn parse(game: &ChessGame, san: &str) -> Result<ChessMove, SanError> {
+
+    let board = game.board();
+    let color = game.active_player();
+
+    // check/mate markers and annotations like "!?" don't change the move
+    let text = san.trim_end_matches(['+', '#', '!', '?']);
+
+    let castling_file = match text {
+        "O-O" | "0-0" => Some(6),
+        "O-O-O" | "0-0-0" => Some(2),
+        _ => None,
+    };
+
+    if let Some(file) = castling_file {
+
+        let king = king_square(board, color).ok_or(SanError::NoMatch)?;
+        let to = square(file, king.rank.get());
+
+        return Ok(ChessMove { piece_movement: PieceMovement { from: king, to }, promotion: None });
+    }
+
+    // "e8=Q", but also the older "e8Q"
+    let (text, promotion) = match text.char_indices().last() {
+        Some((i, c)) if i >= 2 && "NBRQ".contains(c) => {
+            let promotion = match c {
+                'N' => PromotionType::Knight,
+                'B' => PromotionType::Bishop,
+                'R' => PromotionType::Rook,
+                _ => PromotionType::Queen,
+            };
+            (text[..i].trim_end_matches('='), Some(promotion))
+        }
+        _ => (text, None),
+    };
+
+    let (piece_type, rest) = match text.chars().next() {
+        Some('N') => (PieceType::Knight, &text[1..]),
+        Some('B') => (PieceType::Bishop, &text[1..]),
+        Some('R') => (PieceType::Rook, &text[1..]),
+        Some('Q') => (PieceType::Queen, &text[1..]),
+        Some('K') => (PieceType::King, &text[1..]),
+        Some(_) => (PieceType::Pawn, text),
+        None => return Err(SanError::Malformed),
+    };
+
+    let rest: String = rest.chars().filter(|&c| c != 'x').collect();
+
+    if rest.len() < 2 || !rest.is_ascii() {
+        return Err(SanError::Malformed);
+    }
+
+    let (hint, target) = rest.split_at(rest.len() - 2);
+    let to = parse_square(target).ok_or(SanError::Malformed)?;
+
+    // disambiguation: a file, a rank or a whole square
+    let mut from_file = None;
+    let mut from_rank = None;
+
+    for c in hint.chars() {
+        match c {
+            'a'..='h' if from_file.is_none() => from_file = Some(c as u8 - b'a'),
+            '1'..='8' if from_rank.is_none() => from_rank = Some(c as u8 - b'1'),
+            _ => return Err(SanError::Malformed),
+        }
+    }
+
+    let piece = Piece { piece_type, player: color };
+
+    let candidates: Vec<BoardPosition> = all_squares()
+        .filter(|&pos| board.get_piece(pos) == Some(piece))
+        .filter(|pos| from_file.is_none_or(|file| pos.file.get() == file))
+        .filter(|pos| from_rank.is_none_or(|rank| pos.rank.get() == rank))
+        .filter(|&pos| game.available_moves(pos).get(to))
+        .collect();
+
+    match candidates[..] {
+        [from] => Ok(ChessMove { piece_movement: PieceMovement { from, to }, promotion }),
+        [] => Err(SanError::NoMatch),
+        _ => Err(SanError::Ambiguous),
+    }
+}
+
+// "#" for mate, "+" for check, game is the game *after* the move
+pub fn check_suffix(game: &ChessGame) -> &'static str {
+
+    match game.game_status() {
+        GameStatus::Win(_, _) if in_check(game.board(), game.active_player()) => "#",
+        _ if in_check(game.board(), game.active_player()) => "+",
+        _ => "",
+    }
+}
+
+pub fn king_square(board: &Board, color: PlayerColor) -> Option<BoardPosition> {
+
+    all_squares().find(|&pos| match board.get_piece(pos) {
+        Some(piece) => piece.piece_type == PieceType::King && piece.player == color,
+        None => false,
+    })
+}
+
+pub fn in_check(board: &Board, color: PlayerColor) -> bool {
+
+    match king_square(board, color) {
+        Some(king) => is_attacked(board, king, opponent_color(color)),
+        None => false,
+    }
+}
+
+// whether any piece of color `by` attacks the target square
+pub fn is_attacked(board: &Board, target: BoardPosition, by: PlayerColor) -> bool {
+
+    let file = target.file.get() as i8;
+    let rank = target.rank.get() as i8;
+
+    let piece_at = |df: i8, dr: i8| {
+        let (f, r) = (file + df, rank + dr);
+        if !(0..8).contains(&f) || !(0..8).contains(&r) {
+            return None;
+        }
+        board.get_piece(square(f as u8, r as u8))
+    };
+
+    let is = |df: i8, dr: i8, piece_types: &[PieceType]| match piece_at(df, dr) {
+        Some(piece) => piece.player == by && piece_types.contains(&piece.piece_type),
+        None => false,
+    };
+
+    // pawns attack diagonally forward, so look one rank behind the target
+    let pawn_rank = match by {
+        PlayerColor::White => -1,
+        PlayerColor::Black => 1,
+    };
+    if is(-1, pawn_rank, &[PieceType::Pawn]) || is(1, pawn_rank, &[PieceType::Pawn]) {
+        return true;
+    }
+
+    let knight_jumps = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+    if knight_jumps.iter().any(|&(df, dr)| is(df, dr, &[PieceType::Knight])) {
+        return true;
+    }
+
+    let king_steps = [(1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1)];
+    if king_steps.iter().any(|&(df, dr)| is(df, dr, &[PieceType::King])) {
+        return true;
+    }
+
+    // sliding pieces: walk each direction until something blocks
+    let lines = [
+        ((1, 0), PieceType::Rook), ((-1, 0), PieceType::Rook), ((0, 1), PieceType::Rook), ((0, -1), PieceType::Rook),
+        ((1, 1), PieceType::Bishop), ((1, -1), PieceType::Bishop), ((-1, 1), PieceType::Bishop), ((-1, -1), PieceType::Bishop),
+    ];
+
+    for ((df, dr), slider) in lines {
+
+        for distance in 1..8 {
+
+            let (f, r) = (file + df * distance, rank + dr * distance);
+            if !(0..8).contains(&f) || !(0..8).contains(&r) {
+                break;
+            }
+
+            if let Some(piece) = piece_at(df * distance, dr * distance) {
+                if piece.player == by && (piece.piece_type == slider || piece.piece_type == PieceType::Queen) {
+                    return true;
+                }
+                break;
+            }
+        }
+    }
+
+    false
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::fen;
+
+    fn game(fen: &str) -> ChessGame {
+        fen::new_game(fen).unwrap().0
+    }
+
+    // plays a move written as "e2e4" or "e7e8q" and returns its SAN, check suffix included
+    fn play(game: &mut ChessGame, uci: &str) -> String {
+
+        let promotion = match uci.get(4..) {
+            Some("n") => Some(PromotionType::Knight),
+            Some("b") => Some(PromotionType::Bishop),
+            Some("r") => Some(PromotionType::Rook),
+            Some("q") => Some(PromotionType::Queen),
+            _ => None,
+        };
+        let from = parse_square(&uci[0..2]).unwrap();
+        let to = parse_square(&uci[2..4]).unwrap();
+        let mv = ChessMove { piece_movement: PieceMovement { from, to }, promotion };
+
+        let san = describe(game, mv);
+        assert!(game.do_move(mv).is_ok(), "{} is not legal", uci);
+
+        san + check_suffix(game)
+    }
+
+    fn squares(mv: ChessMove) -> (String, String) {
+        (square_name(mv.piece_movement.from), square_name(mv.piece_movement.to))
+    }
+
+    #[test]
+    fn disambiguates_by_file_rank_or_square() {
+
+        // knights on b1 and f1 both reach d2
+        let knights = game("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1");
+        assert_eq!(squares(parse(&knights, "Nbd2").unwrap()), ("b1".to_string(), "d2".to_string()));
+        assert_eq!(parse(&knights, "Nd2").err(), Some(SanError::Ambiguous));
+        assert_eq!(play(&mut game("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1"), "f1d2"), "Nfd2");
+
+        // rooks on the same file
+        let rooks = game("4k3/R7/8/8/8/R7/8/4K3 w - - 0 1");
+        assert_eq!(squares(parse(&rooks, "R3a5").unwrap()), ("a3".to_string(), "a5".to_string()));
+        assert_eq!(parse(&rooks, "Ra5").err(), Some(SanError::Ambiguous));
+        assert_eq!(play(&mut game("4k3/R7/8/8/8/R7/8/4K3 w - - 0 1"), "a7a5"), "R7a5");
+
+        // queens on c3, c5 and e3 all reach e5: c3 shares a file with one and a rank with the other
+        let queens = "k7/8/8/2Q5/8/2Q1Q3/8/7K w - - 0 1";
+        assert_eq!(squares(parse(&game(queens), "Qc3e5").unwrap()), ("c3".to_string(), "e5".to_string()));
+        assert_eq!(parse(&game(queens), "Qe5").err(), Some(SanError::Ambiguous));
+        assert_eq!(play(&mut game(queens), "c3e5"), "Qc3e5");
+        assert_eq!(play(&mut game(queens), "c5e5"), "Q5e5");
+        assert_eq!(play(&mut game(queens), "e3e5"), "Qee5");
+    }
+
+    #[test]
+    fn castles_both_ways_for_both_colors() {
+
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+
+        let mut short_first = game(fen);
+        assert_eq!(play(&mut short_first, "e1g1"), "O-O");
+        assert_eq!(play(&mut short_first, "e8c8"), "O-O-O");
+
+        let mut long_first = game(fen);
+        assert_eq!(play(&mut long_first, "e1c1"), "O-O-O");
+        assert_eq!(squares(parse(&long_first, "O-O").unwrap()), ("e8".to_string(), "g8".to_string()));
+        assert_eq!(play(&mut long_first, "e8g8"), "O-O");
+
+        assert_eq!(squares(parse(&game(fen), "O-O-O").unwrap()), ("e1".to_string(), "c1".to_string()));
+        assert_eq!(squares(parse(&game(fen), "0-0").unwrap()), ("e1".to_string(), "g1".to_string()));
+    }
+
+    #[test]
+    fn en_passant_is_a_pawn_capture() {
+
+        let mut game = game("4k3/3p4/8/4P3/8/8/8/4K3 w - - 0 1");
+
+        assert_eq!(play(&mut game, "e1d1"), "Kd1");
+        assert_eq!(play(&mut game, "d7d5"), "d5");
+        assert_eq!(squares(parse(&game, "exd6").unwrap()), ("e5".to_string(), "d6".to_string()));
+        assert_eq!(play(&mut game, "e5d6"), "exd6");
+    }
+
+    #[test]
+    fn promotions_with_and_without_capture() {
+
+        let fen = "2r1k3/1P6/8/8/8/8/8/4K3 w - - 0 1";
+
+        assert_eq!(play(&mut game(fen), "b7b8n"), "b8=N");
+        assert_eq!(play(&mut game(fen), "b7b8q"), "b8=Q");
+        assert_eq!(play(&mut game(fen), "b7c8q"), "bxc8=Q+");
+        assert_eq!(play(&mut game(fen), "b7c8r"), "bxc8=R+");
+
+        let mv = parse(&game(fen), "bxc8=Q+").unwrap();
+        assert_eq!(squares(mv), ("b7".to_string(), "c8".to_string()));
+        assert!(matches!(mv.promotion, Some(PromotionType::Queen)));
+
+        // the older form without "="
+        assert!(matches!(parse(&game(fen), "b8N").unwrap().promotion, Some(PromotionType::Knight)));
+        assert!(parse(&game(fen), "b8").unwrap().promotion.is_none());
+    }
+
+    #[test]
+    fn check_and_mate_suffixes() {
+
+        // the rook check can be blocked on the back rank, the one from b8 can't
+        assert_eq!(play(&mut game("6k1/5ppp/8/8/8/8/8/1R2K3 w - - 0 1"), "b1b8"), "Rb8#");
+        assert_eq!(play(&mut game("6k1/5p1p/8/8/8/8/8/1R2K3 w - - 0 1"), "b1b8"), "Rb8+");
+        assert_eq!(play(&mut game("6k1/5ppp/8/8/8/8/8/1R2K3 w - - 0 1"), "b1b7"), "Rb7");
+
+        let mut mated = game("6k1/5ppp/8/8/8/8/8/1R2K3 w - - 0 1");
+        play(&mut mated, "b1b8");
+        assert!(in_check(mated.board(), PlayerColor::Black));
+        assert!(!in_check(mated.board(), PlayerColor::White));
+    }
+
+    #[test]
+    fn attacks_stop_at_the_first_piece() {
+
+        let (board, _) = fen::parse("4k3/8/8/4p3/R1n5/8/3P4/4K3").unwrap();
+        let at = |name: &str| parse_square(name).unwrap();
+
+        assert!(is_attacked(&board, at("b4"), PlayerColor::White));
+        assert!(is_attacked(&board, at("c4"), PlayerColor::White));
+        assert!(!is_attacked(&board, at("d4"), PlayerColor::White));
+
+        // pawns take diagonally forward, not straight ahead
+        assert!(is_attacked(&board, at("e3"), PlayerColor::White));
+        assert!(!is_attacked(&board, at("d3"), PlayerColor::White));
+        assert!(is_attacked(&board, at("d4"), PlayerColor::Black));
+        assert!(is_attacked(&board, at("f4"), PlayerColor::Black));
+        assert!(!is_attacked(&board, at("e4"), PlayerColor::Black));
+
+        // the knight on c4 and the king on e8
+        assert!(is_attacked(&board, at("d2"), PlayerColor::Black));
+        assert!(is_attacked(&board, at("d7"), PlayerColor::Black));
+        assert!(!in_check(&board, PlayerColor::White));
+    }
+
+}
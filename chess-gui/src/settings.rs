@@ -0,0 +1,92 @@
+// display options that can be changed while the game is running
+//
+// L: show/hide the legal moves of the selected piece
+// C: show/hide the board coordinates
+// M: move animation speed, off / fast / normal / slow
+// F3: show/hide how long drawing a frame takes
+// P: next piece set
+// B: next board theme
+//
+// they are kept in settings.txt in the user config directory (see config.rs for the format)
+
+use std::io::Write;
+
+use ggez::Context;
+
+use crate::animation::AnimationSpeed;
+use crate::config;
+
+
+const PATH: &str = "/settings.txt";
+
+pub struct Settings {
+    pub show_legal_moves: bool,
+    pub show_coordinates: bool,
+    pub show_frame_time: bool,
+    pub animation_speed: AnimationSpeed,
+    pub piece_set: String, // directory name under resources/pieces
+    pub board_theme: String, // name of a board theme, see theme.rs
+}
+
+impl Default for Settings {
+
+    fn default() -> Self {
+        Settings {
+            show_legal_moves: true,
+            show_coordinates: true,
+            show_frame_time: false,
+            animation_speed: AnimationSpeed::Normal,
+            piece_set: "classic".to_string(),
+            board_theme: "gray".to_string(),
+        }
+    }
+}
+
+impl Settings {
+
+    // defaults for whatever is missing from the file
+    pub fn load(ctx: &Context) -> Self {
+
+        let mut settings = Settings::default();
+
+        let Some(entries) = config::load(ctx, PATH) else {
+            return settings;
+        };
+
+        if let Some(show) = entries.get("show_legal_moves").and_then(|value| config::parse_bool(value)) {
+            settings.show_legal_moves = show;
+        }
+        if let Some(show) = entries.get("show_coordinates").and_then(|value| config::parse_bool(value)) {
+            settings.show_coordinates = show;
+        }
+        if let Some(speed) = entries.get("animation_speed").and_then(|value| AnimationSpeed::parse(value)) {
+            settings.animation_speed = speed;
+        }
+        if let Some(piece_set) = entries.get("piece_set") {
+            settings.piece_set = piece_set.clone();
+        }
+        if let Some(board_theme) = entries.get("board_theme") {
+            settings.board_theme = board_theme.clone();
+        }
+
+        settings
+    }
+
+    pub fn save(&self, ctx: &Context) {
+
+        let text = config::write(&[
+            ("show_legal_moves", self.show_legal_moves.to_string()),
+            ("show_coordinates", self.show_coordinates.to_string()),
+            ("animation_speed", self.animation_speed.name().to_string()),
+            ("piece_set", self.piece_set.clone()),
+            ("board_theme", self.board_theme.clone()),
+        ]);
+
+        let result = ctx.fs.create(PATH).and_then(|mut file| Ok(file.write_all(text.as_bytes())?));
+
+        if let Err(e) = result {
+            println!("Could not save settings: {}", e);
+        }
+    }
+
+}
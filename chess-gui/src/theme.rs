@@ -0,0 +1,156 @@
+// colors of the board, and of everything drawn on top of it
+//
+// board themes are a few built-in color schemes plus the directories under resources/boards
+// with a manifest.txt:
+//
+//   name = Ocean
+//   light = #dee3e6
+//   dark = #8ca2ad
+//   texture = board.png # optional, stretched over the whole board instead of the squares
+//
+// the highlights can be changed too, each one is optional:
+//
+//   selected = #ffff0080
+//   last_move = #50a0ff6e
+//   check = #ff0000c8
+//   legal_move = #147828aa
+
+use ggez::graphics::{Color, Image};
+use ggez::Context;
+
+use crate::config;
+
+
+// the highlights drawn on top of the squares, part of a board theme
+pub struct Theme {
+    pub selected: Color, // the square of the selected piece
+    pub last_move: Color, // from and to square of the move just played
+    pub check: Color, // glow around the king in check
+    pub legal_move: Color, // dots and capture rings
+}
+
+impl Default for Theme {
+
+    fn default() -> Self {
+        Theme {
+            selected: Color::from_rgba(255, 255, 0, 128),
+            last_move: Color::from_rgba(80, 160, 255, 110),
+            check: Color::from_rgba(255, 0, 0, 200),
+            legal_move: Color::from_rgba(20, 120, 40, 170),
+        }
+    }
+}
+
+pub struct BoardTheme {
+    pub id: String, // what the settings file remembers
+    pub name: String,
+    pub light: Color,
+    pub dark: Color,
+    pub texture: Option<Image>,
+    pub highlights: Theme,
+}
+
+impl BoardTheme {
+
+    fn built_in(id: &str, name: &str, light: (u8, u8, u8), dark: (u8, u8, u8)) -> Self {
+        BoardTheme {
+            id: id.to_string(),
+            name: name.to_string(),
+            light: light.into(),
+            dark: dark.into(),
+            texture: None,
+            highlights: Theme::default(),
+        }
+    }
+
+}
+
+// "#rrggbb" or "#rrggbbaa"
+pub fn parse_color(text: &str) -> Option<Color> {
+
+    let hex = text.strip_prefix('#')?;
+
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+        return None;
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    let alpha = if hex.len() == 8 { channel(6)? } else { 255 };
+
+    Some(Color::from_rgba(channel(0)?, channel(2)?, channel(4)?, alpha))
+}
+
+// the built-in themes first, the gray one being the default
+pub fn board_themes(ctx: &Context) -> Vec<BoardTheme> {
+
+    let mut themes = vec![
+        BoardTheme::built_in("gray", "Gray", (220, 220, 220), (50, 50, 50)),
+        BoardTheme::built_in("brown", "Brown", (240, 217, 181), (181, 136, 99)),
+        BoardTheme::built_in("green", "Green", (238, 238, 210), (118, 150, 86)),
+        BoardTheme::built_in("blue", "Blue", (222, 227, 230), (140, 162, 173)),
+    ];
+
+    for manifest in config::manifests(ctx, "/boards") {
+
+        let color = |key: &str| manifest.entries.get(key).and_then(|value| parse_color(value));
+
+        let (Some(light), Some(dark)) = (color("light"), color("dark")) else {
+            println!("Board theme {}: light and dark need to be colors like #f0d9b5", manifest.id);
+            continue;
+        };
+
+        // a missing texture falls back to the colors
+        let texture = manifest.entries.get("texture").and_then(|file| {
+            Image::from_path(ctx, manifest.dir.join(file))
+                .map_err(|e| println!("Board theme {}: could not load {}: {}", manifest.id, file, e))
+                .ok()
+        });
+
+        // the default for highlights that are missing or not a color
+        let highlight = |key: &str, default: Color| match manifest.entries.get(key) {
+            Some(value) => parse_color(value).unwrap_or_else(|| {
+                println!("Board theme {}: {} = {} is not a color like #ffff0080", manifest.id, key, value);
+                default
+            }),
+            None => default,
+        };
+
+        let defaults = Theme::default();
+        let highlights = Theme {
+            selected: highlight("selected", defaults.selected),
+            last_move: highlight("last_move", defaults.last_move),
+            check: highlight("check", defaults.check),
+            legal_move: highlight("legal_move", defaults.legal_move),
+        };
+
+        themes.push(BoardTheme {
+            name: manifest.entries.get("name").cloned().unwrap_or_else(|| manifest.id.clone()),
+            id: manifest.id,
+            light,
+            dark,
+            texture,
+            highlights,
+        });
+    }
+
+    themes
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn parses_hex_colors() {
+
+        assert_eq!(parse_color("#f0d9b5"), Some(Color::from_rgb(240, 217, 181)));
+        assert_eq!(parse_color("#00000080"), Some(Color::from_rgba(0, 0, 0, 128)));
+
+        assert_eq!(parse_color("f0d9b5"), None);
+        assert_eq!(parse_color("#f0d9b"), None);
+        assert_eq!(parse_color("#f0d9zz"), None);
+    }
+
+}